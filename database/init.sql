@@ -9,33 +9,219 @@ CREATE TYPE robot_ident AS (
     name    TEXT
 );
 
+-- The platforms that robot groups are published on. Groups added with `sbb robot add` which were
+-- not published anywhere are "manual".
+CREATE TYPE robot_platform AS ENUM ('twitter', 'activitypub', 'manual');
+
+-- A group of robots published together, such as the robots of "558/9) Salt- and Pepperbots". The fields
+-- which the robots of a group share are stored here once rather than in every robot.
+CREATE TABLE robot_groups (
+    id                SERIAL4 PRIMARY KEY,
+    platform          robot_platform NOT NULL,
+    -- Identifies the group's post on its platform: the tweet id for Twitter, or the object id (a url)
+    -- for ActivityPub. NULL for manual groups.
+    source_ref        TEXT,
+    -- The source reference of Twitter groups as a number, for the commands which only deal with tweets
+    tweet_id          INT8 UNIQUE GENERATED ALWAYS AS (
+        CASE WHEN platform = 'twitter' THEN source_ref::INT8 END
+    ) STORED,
+    tweet_time        TIMESTAMP WITH TIME ZONE NOT NULL,
+    image_url         TEXT NOT NULL,
+    body              TEXT NOT NULL,
+    alt               TEXT,
+    content_warnings  TEXT[] NOT NULL DEFAULT '{}',
+    custom_alt        TEXT,
+    image_path        TEXT,
+    image_thumb_path  TEXT,
+    -- When `sbb verify-tweets` found that the tweet no longer exists
+    deleted_upstream_at  TIMESTAMP WITH TIME ZONE,
+    UNIQUE (platform, source_ref)
+);
+
+CREATE INDEX ix_robot_groups_tweet_time ON robot_groups USING btree (tweet_time);
+
+CREATE INDEX ix_robot_groups_content_warnings ON robot_groups USING gin (content_warnings);
+
 CREATE TABLE robots (
     id                robot_ident PRIMARY KEY,
+    group_id          INT4 NOT NULL REFERENCES robot_groups (id) ON DELETE CASCADE,
     prefix            TEXT NOT NULL,
     suffix            TEXT NOT NULL,
     plural            TEXT,
-    tweet_id          INT8 NOT NULL,
+    needs_review      BOOLEAN NOT NULL DEFAULT FALSE
+);
+
+CREATE INDEX ix_robots_group_id ON robots USING btree (group_id);
+
+-- Other ids which refer to robots, such as alternative spellings of their names or the names they had
+-- before being redrawn. Commands which take robot ids also accept these aliases.
+CREATE TABLE robot_aliases (
+    alias     robot_ident PRIMARY KEY,
+    robot_id  robot_ident NOT NULL REFERENCES robots (id) ON DELETE CASCADE ON UPDATE CASCADE
+);
+
+CREATE INDEX ix_robot_aliases_robot_id ON robot_aliases USING btree (robot_id);
+
+-- TODO: replace with elasticsearch
+-- CREATE INDEX ix_robots_ident_trgm ON robots USING gin (ident gin_trgm_ops);
+
+-- All of the media of each robot group in the order they appear in the tweet. The media at position 0
+-- is the group's primary image, which is also stored in the robot_groups table.
+CREATE TABLE robot_media (
+    group_id          INT4 NOT NULL REFERENCES robot_groups (id) ON DELETE CASCADE,
+    position          INT4 NOT NULL,
+    media_type        TEXT NOT NULL,
+    url               TEXT NOT NULL,
+    alt               TEXT,
+    width             INT4,
+    height            INT4,
+    duration_ms       INT8,
+    image_path        TEXT,
+    image_thumb_path  TEXT,
+    video_path        TEXT,
+    PRIMARY KEY (group_id, position)
+);
+
+-- The encodings that animated gif and video robot media are available in.
+CREATE TABLE robot_media_variants (
+    group_id      INT4 NOT NULL,
+    position      INT4 NOT NULL,
+    variant       INT4 NOT NULL,
+    content_type  TEXT NOT NULL,
+    bitrate       INT8,
+    url           TEXT NOT NULL,
+    PRIMARY KEY (group_id, position, variant),
+    FOREIGN KEY (group_id, position) REFERENCES robot_media (group_id, position) ON DELETE CASCADE
+);
+
+CREATE TABLE tweet_hashtags (
+    tweet_id  INT8 NOT NULL,
+    position  INT4 NOT NULL,
+    tag       TEXT NOT NULL,
+    PRIMARY KEY (tweet_id, position)
+);
+
+CREATE INDEX ix_tweet_hashtags_tag ON tweet_hashtags USING btree (lower(tag));
+
+CREATE TABLE tweet_mentions (
+    tweet_id  INT8 NOT NULL,
+    position  INT4 NOT NULL,
+    user_id   INT8 NOT NULL,
+    handle    TEXT NOT NULL,
+    PRIMARY KEY (tweet_id, position)
+);
+
+CREATE INDEX ix_tweet_mentions_user_id ON tweet_mentions USING btree (user_id);
+
+CREATE TABLE tweet_urls (
+    tweet_id      INT8 NOT NULL,
+    position      INT4 NOT NULL,
+    url           TEXT NOT NULL,
+    expanded_url  TEXT NOT NULL,
+    display_url   TEXT NOT NULL,
+    PRIMARY KEY (tweet_id, position)
+);
+
+-- The tweet text and media as they were given to the parser, used to reparse robots without fetching
+-- the tweets again.
+CREATE TABLE raw_tweets (
+    tweet_id    INT8 PRIMARY KEY,
+    tweet_time  TIMESTAMP WITH TIME ZONE NOT NULL,
+    full_text   TEXT NOT NULL,
+    entities    JSONB NOT NULL,
+    media       JSONB NOT NULL,
+    stored_at   TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT now()
+);
+
+-- The previous values of robots which were changed by `sbb fetch --update` or `sbb timeline --update`
+CREATE TABLE robot_history (
+    id                SERIAL4 PRIMARY KEY,
+    robot_id          robot_ident NOT NULL REFERENCES robots (id) ON DELETE CASCADE ON UPDATE CASCADE,
+    changed_at        TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT now(),
+    prefix            TEXT NOT NULL,
+    suffix            TEXT NOT NULL,
+    plural            TEXT,
+    needs_review      BOOLEAN NOT NULL
+);
+
+CREATE INDEX ix_robot_history_robot_id ON robot_history USING btree (robot_id);
+
+-- The previous values of robot groups which were changed by `sbb fetch --update` or
+-- `sbb timeline --update`
+CREATE TABLE robot_group_history (
+    id                SERIAL4 PRIMARY KEY,
+    group_id          INT4 NOT NULL REFERENCES robot_groups (id) ON DELETE CASCADE,
+    changed_at        TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT now(),
     tweet_time        TIMESTAMP WITH TIME ZONE NOT NULL,
     image_url         TEXT NOT NULL,
     body              TEXT NOT NULL,
     alt               TEXT,
-    content_warning   TEXT,
-    custom_alt        TEXT,
-    image_path        TEXT,
-    image_thumb_path  TEXT
+    content_warnings  TEXT[] NOT NULL
 );
 
--- This is used for preempting duplicates, may not need this any more? (it's ok for there to be conflicts now)
-CREATE INDEX ix_robots_tweet_id ON robots USING btree (tweet_id);
+CREATE INDEX ix_robot_group_history_group_id ON robot_group_history USING btree (group_id);
 
-CREATE INDEX ix_robots_tweet_time ON robots USING btree (tweet_time);
+-- Tweets which could not be stored as robots, kept for review with `sbb quarantine`. Rows are removed
+-- when their tweet is later stored successfully.
+CREATE TABLE quarantine (
+    tweet_id       INT8 PRIMARY KEY,
+    tweet_time     TIMESTAMP WITH TIME ZONE NOT NULL,
+    text           TEXT NOT NULL,
+    reason         TEXT NOT NULL,
+    first_seen_at  TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT now(),
+    last_seen_at   TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT now(),
+    dismissed      BOOLEAN NOT NULL DEFAULT FALSE
+);
 
--- TODO: replace with elasticsearch
--- CREATE INDEX ix_robots_ident_trgm ON robots USING gin (ident gin_trgm_ops);
+-- Tweets which were requested by `sbb fetch` but not returned, e.g. because they were deleted, are
+-- protected or belong to a suspended account. Later fetches skip them unless --retry-unavailable is
+-- given, and rows are removed when their tweet is returned again.
+CREATE TABLE unavailable_tweets (
+    tweet_id       INT8 PRIMARY KEY,
+    first_seen_at  TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT now(),
+    last_seen_at   TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT now()
+);
+
+-- Runs of `sbb fetch`, split into the batches of Tweet ids requested together, so that a run which
+-- was interrupted can be continued with `sbb fetch --resume`
+CREATE TABLE fetch_jobs (
+    id                 SERIAL4 PRIMARY KEY,
+    created_at         TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT now(),
+    finished_at        TIMESTAMP WITH TIME ZONE,
+    -- The options the job was started with, which are used again when it is resumed
+    update_robots      BOOL NOT NULL,
+    strict             BOOL NOT NULL,
+    retry_unavailable  BOOL NOT NULL,
+    overrides_path     TEXT
+);
+
+CREATE TABLE fetch_job_batches (
+    job_id        INT4 NOT NULL REFERENCES fetch_jobs (id) ON DELETE CASCADE,
+    batch         INT4 NOT NULL,
+    tweet_ids     INT8[] NOT NULL,
+    completed_at  TIMESTAMP WITH TIME ZONE,
+    -- The error from the most recent failed attempt, if any
+    error         TEXT,
+    PRIMARY KEY (job_id, batch)
+);
+
+-- Manual changes made to robots with `sbb robot`, with the whole robot row before and after each change
+CREATE TABLE robot_edits (
+    id          SERIAL4 PRIMARY KEY,
+    -- Not a foreign key, so that removed robots keep their edits
+    robot_id    robot_ident NOT NULL,
+    action      TEXT NOT NULL,
+    edited_by   TEXT NOT NULL,
+    edited_at   TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT now(),
+    old_values  JSONB,
+    new_values  JSONB
+);
+
+CREATE INDEX ix_robot_edits_robot_id ON robot_edits USING btree (robot_id);
 
 CREATE TABLE past_dailies (
     id         SERIAL4 PRIMARY KEY,
-    robot_id   robot_ident NOT NULL REFERENCES robots (id) ON DELETE CASCADE,
+    robot_id   robot_ident NOT NULL REFERENCES robots (id) ON DELETE CASCADE ON UPDATE CASCADE,
     posted_on  DATE NOT NULL
 );
 
@@ -44,7 +230,7 @@ CREATE INDEX ix_past_dailies_posted_on ON past_dailies USING btree (posted_on);
 
 CREATE TABLE scheduled_dailies (
     id        SERIAL4 PRIMARY KEY,
-    robot_id  robot_ident NOT NULL REFERENCES robots (id) ON DELETE CASCADE,
+    robot_id  robot_ident NOT NULL REFERENCES robots (id) ON DELETE CASCADE ON UPDATE CASCADE,
     post_on   DATE NOT NULL UNIQUE
 );
 
@@ -0,0 +1,231 @@
+-- Upgrades a database created with the original schema, where each robot was a single row of the
+-- robots table with its own tweet_id, to the schema in init.sql. Existing robots are grouped by
+-- their tweet and their content warnings, images and dailies are kept. Tables which did not exist
+-- before are created empty.
+--
+-- Run it once against the existing database, e.g.
+--     docker-compose exec -T database psql -U sbb -d sbb -v ON_ERROR_STOP=1 < database/upgrade.sql
+
+BEGIN;
+
+CREATE TYPE robot_platform AS ENUM ('twitter', 'activitypub', 'manual');
+
+CREATE TABLE robot_groups (
+    id                SERIAL4 PRIMARY KEY,
+    platform          robot_platform NOT NULL,
+    source_ref        TEXT,
+    tweet_id          INT8 UNIQUE GENERATED ALWAYS AS (
+        CASE WHEN platform = 'twitter' THEN source_ref::INT8 END
+    ) STORED,
+    tweet_time        TIMESTAMP WITH TIME ZONE NOT NULL,
+    image_url         TEXT NOT NULL,
+    body              TEXT NOT NULL,
+    alt               TEXT,
+    content_warnings  TEXT[] NOT NULL DEFAULT '{}',
+    custom_alt        TEXT,
+    image_path        TEXT,
+    image_thumb_path  TEXT,
+    deleted_upstream_at  TIMESTAMP WITH TIME ZONE,
+    UNIQUE (platform, source_ref)
+);
+
+CREATE INDEX ix_robot_groups_tweet_time ON robot_groups USING btree (tweet_time);
+
+CREATE INDEX ix_robot_groups_content_warnings ON robot_groups USING gin (content_warnings);
+
+-- Robots from the same tweet share a group, which takes its fields from the lowest-numbered robot
+INSERT INTO robot_groups (
+    platform, source_ref, tweet_time, image_url, body, alt, content_warnings, custom_alt, image_path,
+    image_thumb_path
+)
+SELECT DISTINCT ON (tweet_id)
+    'twitter', tweet_id::TEXT, tweet_time, image_url, body, alt,
+    CASE WHEN content_warning IS NULL THEN '{}' ELSE ARRAY[content_warning] END,
+    custom_alt, image_path, image_thumb_path
+FROM robots
+ORDER BY tweet_id, id;
+
+ALTER TABLE robots
+    ADD COLUMN group_id INT4 REFERENCES robot_groups (id) ON DELETE CASCADE,
+    ADD COLUMN needs_review BOOLEAN NOT NULL DEFAULT FALSE;
+
+UPDATE robots SET group_id = robot_groups.id
+FROM robot_groups
+WHERE robot_groups.tweet_id = robots.tweet_id;
+
+ALTER TABLE robots ALTER COLUMN group_id SET NOT NULL;
+
+DROP INDEX ix_robots_tweet_id;
+DROP INDEX ix_robots_tweet_time;
+
+ALTER TABLE robots
+    DROP COLUMN tweet_id,
+    DROP COLUMN tweet_time,
+    DROP COLUMN image_url,
+    DROP COLUMN body,
+    DROP COLUMN alt,
+    DROP COLUMN content_warning,
+    DROP COLUMN custom_alt,
+    DROP COLUMN image_path,
+    DROP COLUMN image_thumb_path;
+
+CREATE INDEX ix_robots_group_id ON robots USING btree (group_id);
+
+-- Renaming a robot now carries its dailies with it
+ALTER TABLE past_dailies
+    DROP CONSTRAINT past_dailies_robot_id_fkey,
+    ADD FOREIGN KEY (robot_id) REFERENCES robots (id) ON DELETE CASCADE ON UPDATE CASCADE;
+
+ALTER TABLE scheduled_dailies
+    DROP CONSTRAINT scheduled_dailies_robot_id_fkey,
+    ADD FOREIGN KEY (robot_id) REFERENCES robots (id) ON DELETE CASCADE ON UPDATE CASCADE;
+
+CREATE TABLE robot_aliases (
+    alias     robot_ident PRIMARY KEY,
+    robot_id  robot_ident NOT NULL REFERENCES robots (id) ON DELETE CASCADE ON UPDATE CASCADE
+);
+
+CREATE INDEX ix_robot_aliases_robot_id ON robot_aliases USING btree (robot_id);
+
+CREATE TABLE robot_media (
+    group_id          INT4 NOT NULL REFERENCES robot_groups (id) ON DELETE CASCADE,
+    position          INT4 NOT NULL,
+    media_type        TEXT NOT NULL,
+    url               TEXT NOT NULL,
+    alt               TEXT,
+    width             INT4,
+    height            INT4,
+    duration_ms       INT8,
+    image_path        TEXT,
+    image_thumb_path  TEXT,
+    video_path        TEXT,
+    PRIMARY KEY (group_id, position)
+);
+
+-- The original schema only stored each robot's primary image, which becomes the group's first media.
+-- The rest of the media is stored the next time the tweet is fetched with --update.
+INSERT INTO robot_media (group_id, position, media_type, url, alt, image_path, image_thumb_path)
+SELECT id, 0, 'photo', image_url, alt, image_path, image_thumb_path
+FROM robot_groups;
+
+CREATE TABLE robot_media_variants (
+    group_id      INT4 NOT NULL,
+    position      INT4 NOT NULL,
+    variant       INT4 NOT NULL,
+    content_type  TEXT NOT NULL,
+    bitrate       INT8,
+    url           TEXT NOT NULL,
+    PRIMARY KEY (group_id, position, variant),
+    FOREIGN KEY (group_id, position) REFERENCES robot_media (group_id, position) ON DELETE CASCADE
+);
+
+CREATE TABLE tweet_hashtags (
+    tweet_id  INT8 NOT NULL,
+    position  INT4 NOT NULL,
+    tag       TEXT NOT NULL,
+    PRIMARY KEY (tweet_id, position)
+);
+
+CREATE INDEX ix_tweet_hashtags_tag ON tweet_hashtags USING btree (lower(tag));
+
+CREATE TABLE tweet_mentions (
+    tweet_id  INT8 NOT NULL,
+    position  INT4 NOT NULL,
+    user_id   INT8 NOT NULL,
+    handle    TEXT NOT NULL,
+    PRIMARY KEY (tweet_id, position)
+);
+
+CREATE INDEX ix_tweet_mentions_user_id ON tweet_mentions USING btree (user_id);
+
+CREATE TABLE tweet_urls (
+    tweet_id      INT8 NOT NULL,
+    position      INT4 NOT NULL,
+    url           TEXT NOT NULL,
+    expanded_url  TEXT NOT NULL,
+    display_url   TEXT NOT NULL,
+    PRIMARY KEY (tweet_id, position)
+);
+
+CREATE TABLE raw_tweets (
+    tweet_id    INT8 PRIMARY KEY,
+    tweet_time  TIMESTAMP WITH TIME ZONE NOT NULL,
+    full_text   TEXT NOT NULL,
+    entities    JSONB NOT NULL,
+    media       JSONB NOT NULL,
+    stored_at   TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT now()
+);
+
+CREATE TABLE robot_history (
+    id                SERIAL4 PRIMARY KEY,
+    robot_id          robot_ident NOT NULL REFERENCES robots (id) ON DELETE CASCADE ON UPDATE CASCADE,
+    changed_at        TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT now(),
+    prefix            TEXT NOT NULL,
+    suffix            TEXT NOT NULL,
+    plural            TEXT,
+    needs_review      BOOLEAN NOT NULL
+);
+
+CREATE INDEX ix_robot_history_robot_id ON robot_history USING btree (robot_id);
+
+CREATE TABLE robot_group_history (
+    id                SERIAL4 PRIMARY KEY,
+    group_id          INT4 NOT NULL REFERENCES robot_groups (id) ON DELETE CASCADE,
+    changed_at        TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT now(),
+    tweet_time        TIMESTAMP WITH TIME ZONE NOT NULL,
+    image_url         TEXT NOT NULL,
+    body              TEXT NOT NULL,
+    alt               TEXT,
+    content_warnings  TEXT[] NOT NULL
+);
+
+CREATE INDEX ix_robot_group_history_group_id ON robot_group_history USING btree (group_id);
+
+CREATE TABLE quarantine (
+    tweet_id       INT8 PRIMARY KEY,
+    tweet_time     TIMESTAMP WITH TIME ZONE NOT NULL,
+    text           TEXT NOT NULL,
+    reason         TEXT NOT NULL,
+    first_seen_at  TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT now(),
+    last_seen_at   TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT now(),
+    dismissed      BOOLEAN NOT NULL DEFAULT FALSE
+);
+
+CREATE TABLE unavailable_tweets (
+    tweet_id       INT8 PRIMARY KEY,
+    first_seen_at  TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT now(),
+    last_seen_at   TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT now()
+);
+
+CREATE TABLE fetch_jobs (
+    id                 SERIAL4 PRIMARY KEY,
+    created_at         TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT now(),
+    finished_at        TIMESTAMP WITH TIME ZONE,
+    update_robots      BOOL NOT NULL,
+    strict             BOOL NOT NULL,
+    retry_unavailable  BOOL NOT NULL,
+    overrides_path     TEXT
+);
+
+CREATE TABLE fetch_job_batches (
+    job_id        INT4 NOT NULL REFERENCES fetch_jobs (id) ON DELETE CASCADE,
+    batch         INT4 NOT NULL,
+    tweet_ids     INT8[] NOT NULL,
+    completed_at  TIMESTAMP WITH TIME ZONE,
+    error         TEXT,
+    PRIMARY KEY (job_id, batch)
+);
+
+CREATE TABLE robot_edits (
+    id          SERIAL4 PRIMARY KEY,
+    robot_id    robot_ident NOT NULL,
+    action      TEXT NOT NULL,
+    edited_by   TEXT NOT NULL,
+    edited_at   TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT now(),
+    old_values  JSONB,
+    new_values  JSONB
+);
+
+CREATE INDEX ix_robot_edits_robot_id ON robot_edits USING btree (robot_id);
+
+COMMIT;
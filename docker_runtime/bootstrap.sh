@@ -2,7 +2,7 @@
 
 if test -n "$SBB_BOOTSTRAP_IDS"; then
     echo "$SBB_BOOTSTRAP_IDS" \
-        | sbb fetch \
+        | sbb fetch --overrides /sbb/overrides.json \
         | sbb image \
             --connect-timeout 30 \
             --request-timeout 300 \
@@ -14,7 +14,7 @@ fi
 
 if test -n "$SBB_BOOTSTRAP_URL"; then
     wget -q -O - "$SBB_BOOTSTRAP_URL" \
-        | sbb fetch \
+        | sbb fetch --overrides /sbb/overrides.json \
         | sbb image \
             --connect-timeout 30 \
             --request-timeout 300 \
@@ -1,6 +1,6 @@
 #!/bin/sh
 
-sbb timeline -n 20 '@smolrobots' \
+sbb timeline -n 20 --overrides /sbb/overrides.json '@smolrobots' \
     | sbb image \
         --connect-timeout 30 \
         --request-timeout 300 \
@@ -0,0 +1,35 @@
+#![no_main]
+
+// smolbotbot is a binary crate, so the parser modules are compiled directly into the fuzz target.
+#[allow(dead_code)]
+#[path = "../../src/parse.rs"]
+mod parse;
+
+#[allow(dead_code)]
+#[path = "../../src/fold.rs"]
+mod fold;
+
+/// Stands in for the real `model` module, which depends on sqlx and goldcrest; the parser only
+/// needs the robot identifier type.
+mod model {
+    use std::fmt;
+
+    #[derive(PartialEq, Eq, Hash, Clone, Debug)]
+    pub struct IdentBuf {
+        pub number: i32,
+        pub name: String,
+    }
+
+    impl fmt::Display for IdentBuf {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(f, "{}/{}", self.number, self.name)
+        }
+    }
+}
+
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|text: &str| {
+    // The invariants are defined alongside the parser so that its property tests check the same ones
+    parse::check_group_invariants(text);
+});
@@ -6,7 +6,8 @@ use serde::{Serialize, Deserialize};
 use sqlx::postgres::PgPool;
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
 
-use crate::model::{self, IdentBuf};
+use crate::ident;
+use crate::model;
 
 #[derive(Parser, Debug)]
 pub(crate) struct Opts {
@@ -67,16 +68,23 @@ async fn import_alt(db_pool: &PgPool, opts: ImportOpts) -> anyhow::Result<()> {
 
     drop(input);
 
-    for entry in entries {
-        let id = entry.robot.parse::<IdentBuf>()
-            .with_context(|| format!("invalid robot id: {}", entry.robot))?;
+    let mut db_conn = db_pool.acquire().await?;
 
-        sqlx::query("UPDATE robots SET custom_alt = $1 WHERE id = $2")
-            .bind(entry.alt)
-            .bind(&id)
-            .execute(db_pool)
+    for entry in entries {
+        let id = ident::resolve(&mut db_conn, &entry.robot)
             .await
-            .with_context(|| format!("failed to update alt text for {}", id))?;
+            .with_context(|| format!("failed to resolve robot {}", entry.robot))?;
+
+        // Alt text describes the image, which is shared by all of the robots in a group
+        sqlx::query(
+            "UPDATE robot_groups SET custom_alt = $1 \
+            WHERE id = (SELECT group_id FROM robots WHERE id = $2)"
+        )
+        .bind(entry.alt)
+        .bind(&id)
+        .execute(&mut db_conn)
+        .await
+        .with_context(|| format!("failed to update alt text for {}", id))?;
     }
 
     Ok(())
@@ -84,8 +92,10 @@ async fn import_alt(db_pool: &PgPool, opts: ImportOpts) -> anyhow::Result<()> {
 
 async fn export_alt(db_pool: &PgPool, opts: ExportOpts) -> anyhow::Result<()> {
     let robots: Vec<model::RobotCustomAltExport> = sqlx::query_as(
-        "SELECT id, custom_alt FROM robots \
-        WHERE custom_alt IS NOT NULL"
+        "SELECT DISTINCT ON (g.id) r.id, g.custom_alt FROM robot_groups g \
+        JOIN robots r ON r.group_id = g.id \
+        WHERE g.custom_alt IS NOT NULL \
+        ORDER BY g.id, r.id"
     )
     .fetch_all(db_pool)
     .await
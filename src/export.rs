@@ -44,7 +44,7 @@ pub(crate) async fn run(db_pool: &PgPool, opts: Opts) -> anyhow::Result<()> {
 }
 
 async fn fetch_ids(db_pool: &PgPool) -> anyhow::Result<Vec<model::TweetId>> {
-    sqlx::query_as("SELECT DISTINCT tweet_id FROM robots")
+    sqlx::query_as("SELECT tweet_id FROM robot_groups WHERE tweet_id IS NOT NULL")
         .fetch_all(db_pool)
         .await
         .context("failed to retrieve tweet ids from database")
@@ -1,19 +1,25 @@
-use std::path::PathBuf;
+use std::collections::HashSet;
+use std::io;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 
-use anyhow::Context;
+use anyhow::{anyhow, Context};
 use clap::Parser;
 use goldcrest::TweetOptions;
+use sqlx::Connection;
 use sqlx::postgres::PgPool;
 use tokio::io::AsyncReadExt;
 
-use crate::model::{self, IdentBuf};
-use crate::scribe::{self, ScribeFailure};
+use crate::model;
+use crate::overrides::ParseOverrides;
+use crate::report::IngestRecorder;
+use crate::scribe::{self, RobotPost, ScribeFailure, ScribeOptions, ScribedPost};
 
 #[derive(Parser, Debug)]
 pub(crate) struct Opts {
     /// The maximum number of tweets that can be requested concurrently. If omitted, all tweets
-    /// will be requested concurrently.
+    /// will be requested concurrently. When starting a new job, this is also the largest number of
+    /// tweets in one of the job's batches.
     #[clap(short, long)]
     batch_size: Option<usize>,
 
@@ -21,157 +27,685 @@ pub(crate) struct Opts {
     #[clap(short, long)]
     verbose: bool,
 
+    /// Skip robot Tweets which parsed with warnings, rather than storing them flagged for review.
+    #[clap(long, conflicts_with = "resume")]
+    strict: bool,
+
+    /// Update robots which have already been stored, rather than skipping their Tweets.
+    #[clap(long, conflicts_with = "resume")]
+    update: bool,
+
+    /// A json file of manual corrections for robot Tweets which cannot be parsed correctly.
+    #[clap(long, conflicts_with = "resume")]
+    overrides: Option<PathBuf>,
+
+    /// Write a json report of what happened to each Tweet, along with totals and timing, to the
+    /// given file.
+    #[clap(long)]
+    report: Option<PathBuf>,
+
+    /// Also request Tweets which were not returned by an earlier fetch, rather than skipping them.
+    #[clap(long, conflicts_with = "resume")]
+    retry_unavailable: bool,
+
+    /// Write the ids of the Tweets which were requested but not returned, e.g. because they have
+    /// been deleted or are protected, to the given file, one per line.
+    #[clap(long)]
+    unavailable_out: Option<PathBuf>,
+
+    /// Write the ids of the Tweets which were not stored because their batch failed to the given
+    /// file, one per line, so that they can be fetched again. Failed batches can also be retried
+    /// with --resume.
+    #[clap(long)]
+    retry_out: Option<PathBuf>,
+
+    /// Continue the fetch job with the given id, fetching only the batches of Tweets which have not
+    /// yet been stored successfully. The job's Tweet ids are used instead of reading new ones, and
+    /// its Tweets are stored with the options the job was started with.
+    #[clap(long, conflicts_with = "file")]
+    resume: Option<i32>,
+
     /// The file to read the Tweet ids from.
     /// If omitted, they will be read from stdin instead.
     file: Option<PathBuf>,
 }
 
+/// The largest number of tweets which can be requested from the Twitter API at once.
+pub(crate) const TWEETS_PER_REQUEST: usize = 100;
+
+/// The options which a fetch job was started with, which are stored with the job so that resuming
+/// it stores the rest of its tweets in the same way.
+#[derive(sqlx::FromRow, Debug)]
+struct JobOptions {
+    update_robots: bool,
+    strict: bool,
+    retry_unavailable: bool,
+    overrides_path: Option<String>,
+}
+
+/// A group of tweet ids belonging to a fetch job which are requested together.
+#[derive(Clone, Debug)]
+struct FetchBatch {
+    index: i32,
+    tweet_ids: Vec<u64>,
+}
+
+/// A batch which could not be fetched or stored, along with the error which caused it to fail.
+#[derive(Debug)]
+struct FailedBatch {
+    batch: FetchBatch,
+    /// The ids of the tweets in the batch which were not stored before the error occurred.
+    remaining: Vec<u64>,
+    error: ScribeFailure,
+}
+
+/// The error which stopped a batch, along with the tweets which were scribed before it occurred.
+/// Each tweet is stored in its own transaction, so those tweets have been stored regardless.
+#[derive(Debug)]
+struct BatchFailure {
+    scribed: Vec<ScribedPost>,
+    /// The ids of the tweets in the batch which were not stored.
+    remaining: Vec<u64>,
+    error: ScribeFailure,
+}
+
+/// The tweets of a batch which was fetched and stored successfully.
+#[derive(Debug)]
+struct BatchResult {
+    scribed: Vec<ScribedPost>,
+    /// The ids of the tweets in the batch which were not returned by the Twitter API.
+    unavailable: Vec<u64>,
+}
+
+#[derive(Default, Debug)]
+struct FetchResults {
+    scribed: Vec<ScribedPost>,
+    unavailable: Vec<u64>,
+    failed: Vec<FailedBatch>,
+}
+
+impl FetchResults {
+    fn extend(&mut self, other: FetchResults) {
+        self.scribed.extend(other.scribed);
+        self.unavailable.extend(other.unavailable);
+        self.failed.extend(other.failed);
+    }
+}
+
 pub(crate) async fn run(
     db_pool: &PgPool,
     au_client: Arc<goldcrest::Client>,
     opts: Opts
 ) -> anyhow::Result<()>
 {
-    let tweet_ids = {
-        let input = match opts.file {
-            Some(input_path) =>
-                tokio::fs::read_to_string(&input_path)
-                    .await
-                    .with_context(|| format!("failed to read input file {}", input_path.to_string_lossy()))?,
-    
-            None => {
-                let mut buf = String::new();
-                tokio::io::stdin()
-                    .read_to_string(&mut buf)
-                    .await
-                    .context("failed to read from stdin")?;
-                buf
-            },
-        };
+    let mut recorder = IngestRecorder::start("fetch");
+
+    let (job_id, job_opts, batches) = match opts.resume {
+        Some(job_id) => {
+            let (job_opts, batches) = load_job(db_pool, job_id).await?;
+            (job_id, job_opts, batches)
+        },
+
+        None => {
+            let job_opts = JobOptions {
+                update_robots: opts.update,
+                strict: opts.strict,
+                retry_unavailable: opts.retry_unavailable,
+                overrides_path: opts.overrides
+                    .as_ref()
+                    .map(|path| path.to_string_lossy().into_owned()),
+            };
+
+            let tweet_ids = read_tweet_ids(
+                db_pool, opts.file, job_opts.update_robots, job_opts.retry_unavailable, &mut recorder
+            ).await?;
+
+            if tweet_ids.is_empty() {
+                eprintln!("no tweets to fetch");
+
+                if let Some(report_path) = opts.report {
+                    recorder.finish().write(&report_path).await?;
+                }
+
+                return Ok(());
+            }
+
+            // Requests are never larger than the limit on concurrently requested tweets
+            let batch_len = opts.batch_size
+                .unwrap_or(TWEETS_PER_REQUEST)
+                .clamp(1, TWEETS_PER_REQUEST);
+
+            let (job_id, batches) = create_job(db_pool, &job_opts, &tweet_ids, batch_len).await?;
+            (job_id, job_opts, batches)
+        },
+    };
+
+    eprintln!("fetch job {}: {} batches to fetch", job_id, batches.len());
+
+    let overrides_path = job_opts.overrides_path.as_deref().map(Path::new);
+    let overrides = Arc::new(ParseOverrides::load(overrides_path).await?);
+
+    let scribe_opts = ScribeOptions {
+        verbose: opts.verbose,
+        strict: job_opts.strict,
+        update: job_opts.update_robots,
+    };
+
+    let results = match opts.batch_size {
+        Some(batch_size) => batched_fetch_and_scribe(au_client, db_pool, job_id, &batches, batch_size, overrides, scribe_opts).await,
+        None => fetch_and_scribe(au_client, db_pool, job_id, &batches, overrides, scribe_opts).await,
+    };
+
+    let FetchResults { scribed, unavailable, failed } = results;
+
+    // Report the robots from every batch even if some failed, since they have been stored
+    // regardless
+    scribe::report_stored(&scribe::stored_robots(&scribed), job_opts.update_robots);
+
+    for failed_batch in &failed {
+        eprintln!(
+            "batch {} failed with {} of its {} tweets not stored: {}",
+            failed_batch.batch.index,
+            failed_batch.remaining.len(),
+            failed_batch.batch.tweet_ids.len(),
+            failed_batch.error
+        );
+    }
+
+    if !unavailable.is_empty() {
+        eprintln!("{} tweets were not returned and have been marked as unavailable", unavailable.len());
+    }
+
+    report_job(db_pool, job_id)
+        .await
+        .with_context(|| format!("failed to get progress of fetch job {}", job_id))?;
+
+    if let Some(retry_path) = &opts.retry_out {
+        let retry_ids = failed.iter().flat_map(|failed_batch| failed_batch.remaining.iter());
+        write_ids(retry_path, retry_ids)
+            .await
+            .with_context(|| format!("failed to write retry file {}", retry_path.to_string_lossy()))?;
+    }
+
+    if let Some(unavailable_path) = &opts.unavailable_out {
+        write_ids(unavailable_path, unavailable.iter())
+            .await
+            .with_context(|| format!("failed to write unavailable file {}", unavailable_path.to_string_lossy()))?;
+    }
+
+    if let Some(report_path) = opts.report {
+        recorder.scribed(&scribed);
+        recorder.not_returned(unavailable);
+
+        for failed_batch in &failed {
+            recorder.failed(failed_batch.remaining.iter().copied(), &failed_batch.error.to_string());
+        }
+
+        recorder.finish().write(&report_path).await?;
+    }
+
+    match failed.len() {
+        0 => Ok(()),
+        n_failed => Err(anyhow!("{} of {} batches failed", n_failed, batches.len())),
+    }
+}
+
+/// Reads the tweet ids to fetch from the given file or stdin, leaving out any which are already
+/// stored unless `update` is set, and any which were found to be unavailable by an earlier fetch
+/// unless `retry_unavailable` is set.
+async fn read_tweet_ids(
+    db_pool: &PgPool,
+    file: Option<PathBuf>,
+    update: bool,
+    retry_unavailable: bool,
+    recorder: &mut IngestRecorder
+) -> anyhow::Result<Vec<u64>>
+{
+    let input = match file {
+        Some(input_path) =>
+            tokio::fs::read_to_string(&input_path)
+                .await
+                .with_context(|| format!("failed to read input file {}", input_path.to_string_lossy()))?,
+
+        None => {
+            let mut buf = String::new();
+            tokio::io::stdin()
+                .read_to_string(&mut buf)
+                .await
+                .context("failed to read from stdin")?;
+            buf
+        },
+    };
+
+    let requested_ids = input
+        .split_ascii_whitespace()
+        .map(|id| id.parse::<u64>()
+            // Convert to i64 for database now rather than parsing as i64 because we want to
+            // error on negative inputs
+            .map(|id| id as i64)
+            .with_context(|| format!(r#"invalid tweet id "{}""#, id)))
+        .collect::<anyhow::Result<Vec<i64>>>()?;
+
+    // Only use tweet ids that are not already in the database, unless we are updating them
+    let mut tweet_ids = sqlx::query_as::<_, model::TweetId>(
+        "SELECT tweet_id FROM UNNEST($1) as tweet_ids(tweet_id) \
+        WHERE $2 OR NOT EXISTS (SELECT 1 FROM robot_groups WHERE robot_groups.tweet_id = tweet_ids.tweet_id)"
+    )
+    .bind(&requested_ids)
+    .bind(update)
+    .fetch_all(db_pool)
+    .await
+    .map(|ids| ids
+        .into_iter()
+        .map(|row| row.tweet_id as u64)
+        .collect::<Vec<u64>>())
+    .context("failed to check for existing tweet ids")?;
+
+    tweet_ids.sort_unstable();
+    tweet_ids.dedup();
 
-        let tweet_ids = input
-            .split_ascii_whitespace()
-            .map(|id| id.parse::<u64>()
-                // Convert to i64 for database now rather than parsing as i64 because we want to
-                // error on negative inputs
-                .map(|id| id as i64)
-                .with_context(|| format!(r#"invalid tweet id "{}""#, id)))
-            .collect::<anyhow::Result<Vec<i64>>>()?;
-
-        // Only use tweet ids that are not already in the database
-        let mut tweet_ids = sqlx::query_as::<_, model::TweetId>(
-            "SELECT tweet_id FROM UNNEST($1) as tweet_ids(tweet_id) \
-            WHERE NOT EXISTS (SELECT 1 FROM robots WHERE robots.tweet_id = tweet_ids.tweet_id)"
+    recorder.already_stored(requested_ids
+        .iter()
+        .map(|&id| id as u64)
+        .collect::<HashSet<_>>()
+        .difference(&tweet_ids.iter().copied().collect())
+        .copied());
+
+    if !retry_unavailable {
+        let unavailable_ids = sqlx::query_scalar::<_, i64>(
+            "SELECT tweet_id FROM unavailable_tweets WHERE tweet_id = ANY($1)"
         )
-        .bind(&tweet_ids)
+        .bind(tweet_ids.iter().map(|&id| id as i64).collect::<Vec<_>>())
         .fetch_all(db_pool)
         .await
-        .map(|ids| ids
-            .into_iter()
-            .map(|row| row.tweet_id as u64)
-            .collect::<Vec<u64>>())
-        .context("failed to check for existing tweet ids")?;
-
-        tweet_ids.sort_unstable();
-        tweet_ids.dedup();
-        tweet_ids
-    };
+        .context("failed to check for unavailable tweet ids")?
+        .into_iter()
+        .map(|id| id as u64)
+        .collect::<HashSet<_>>();
+
+        if !unavailable_ids.is_empty() {
+            eprintln!(
+                "skipping {} tweets which were unavailable in an earlier fetch; use --retry-unavailable to request them",
+                unavailable_ids.len()
+            );
+        }
+
+        tweet_ids.retain(|id| !unavailable_ids.contains(id));
+        recorder.unavailable(unavailable_ids);
+    }
+
+    Ok(tweet_ids)
+}
+
+/// Records a new fetch job for the given tweet ids, split into batches of at most `batch_len`
+/// tweets, and returns its id and batches.
+async fn create_job(
+    db_pool: &PgPool,
+    job_opts: &JobOptions,
+    tweet_ids: &[u64],
+    batch_len: usize
+) -> anyhow::Result<(i32, Vec<FetchBatch>)>
+{
+    let batches = tweet_ids
+        .chunks(batch_len)
+        .enumerate()
+        .map(|(index, tweet_ids)| FetchBatch {
+            index: index as i32,
+            tweet_ids: tweet_ids.to_vec(),
+        })
+        .collect::<Vec<_>>();
+
+    let mut db_conn = db_pool.acquire().await?;
+    let mut tx = db_conn.begin().await?;
+
+    let job_id: i32 = sqlx::query_scalar(
+        "INSERT INTO fetch_jobs (update_robots, strict, retry_unavailable, overrides_path) \
+        VALUES ($1, $2, $3, $4) \
+        RETURNING id"
+    )
+    .bind(job_opts.update_robots)
+    .bind(job_opts.strict)
+    .bind(job_opts.retry_unavailable)
+    .bind(&job_opts.overrides_path)
+    .fetch_one(&mut tx)
+    .await
+    .context("failed to create fetch job")?;
+
+    for batch in &batches {
+        sqlx::query("INSERT INTO fetch_job_batches (job_id, batch, tweet_ids) VALUES ($1, $2, $3)")
+            .bind(job_id)
+            .bind(batch.index)
+            .bind(batch.tweet_ids.iter().map(|&id| id as i64).collect::<Vec<_>>())
+            .execute(&mut tx)
+            .await
+            .context("failed to store fetch job batch")?;
+    }
+
+    tx.commit().await?;
+
+    Ok((job_id, batches))
+}
 
-    let robot_ids = match opts.batch_size {
-        Some(batch_size) => batched_fetch_and_scribe(au_client, db_pool, &tweet_ids, batch_size, opts.verbose).await,
-        None => fetch_and_scribe(au_client, db_pool, &tweet_ids, opts.verbose).await,
-    }.context("failed to fetch some tweets")?;
+/// Returns the options the given fetch job was started with, and its batches which have not yet
+/// been completed.
+async fn load_job(db_pool: &PgPool, job_id: i32) -> anyhow::Result<(JobOptions, Vec<FetchBatch>)> {
+    let job_opts = sqlx::query_as::<_, JobOptions>(
+        "SELECT update_robots, strict, retry_unavailable, overrides_path FROM fetch_jobs WHERE id = $1"
+    )
+    .bind(job_id)
+    .fetch_optional(db_pool)
+    .await
+    .with_context(|| format!("failed to get fetch job {}", job_id))?
+    .ok_or_else(|| anyhow!("fetch job {} does not exist", job_id))?;
 
-    for robot_id in robot_ids {
-        println!("{}", robot_id);
+    let batches: Vec<(i32, Vec<i64>)> = sqlx::query_as(
+        "SELECT batch, tweet_ids FROM fetch_job_batches \
+        WHERE job_id = $1 AND completed_at IS NULL \
+        ORDER BY batch"
+    )
+    .bind(job_id)
+    .fetch_all(db_pool)
+    .await
+    .with_context(|| format!("failed to get batches of fetch job {}", job_id))?;
+
+    let batches = batches
+        .into_iter()
+        .map(|(index, tweet_ids)| FetchBatch {
+            index,
+            tweet_ids: tweet_ids.into_iter().map(|id| id as u64).collect(),
+        })
+        .collect();
+
+    Ok((job_opts, batches))
+}
+
+/// Prints which batches of the fetch job have been completed to stderr, and marks the job as
+/// finished if all of them have.
+async fn report_job(db_pool: &PgPool, job_id: i32) -> anyhow::Result<()> {
+    let batches: Vec<(i32, bool)> = sqlx::query_as(
+        "SELECT batch, completed_at IS NOT NULL FROM fetch_job_batches WHERE job_id = $1 ORDER BY batch"
+    )
+    .bind(job_id)
+    .fetch_all(db_pool)
+    .await?;
+
+    let (completed, incomplete) = batches
+        .into_iter()
+        .partition::<Vec<_>, _>(|&(_, completed)| completed);
+
+    let completed = completed.into_iter().map(|(index, _)| index).collect::<Vec<_>>();
+    let incomplete = incomplete.into_iter().map(|(index, _)| index).collect::<Vec<_>>();
+
+    eprintln!(
+        "fetch job {}: {} of {} batches succeeded ({})",
+        job_id, completed.len(), completed.len() + incomplete.len(), format_ranges(&completed)
+    );
+
+    if incomplete.is_empty() {
+        sqlx::query("UPDATE fetch_jobs SET finished_at = now() WHERE id = $1 AND finished_at IS NULL")
+            .bind(job_id)
+            .execute(db_pool)
+            .await?;
+    } else {
+        eprintln!(
+            "fetch job {}: batches {} did not succeed; continue with `sbb fetch --resume {}`",
+            job_id, format_ranges(&incomplete), job_id
+        );
     }
 
     Ok(())
 }
 
+/// Writes the given tweet ids to a file, one per line.
+async fn write_ids<'a, I>(path: &Path, tweet_ids: I) -> io::Result<()>
+where
+    I: Iterator<Item = &'a u64>,
+{
+    let mut buf = String::new();
+    for tweet_id in tweet_ids {
+        buf.push_str(&tweet_id.to_string());
+        buf.push('\n');
+    }
+
+    tokio::fs::write(path, buf).await
+}
+
+/// Formats a sorted list of batch indexes as comma-separated ranges, e.g. "0-3, 5, 7-8".
+fn format_ranges(indexes: &[i32]) -> String {
+    let mut ranges = Vec::<(i32, i32)>::new();
+
+    for &index in indexes {
+        match ranges.last_mut() {
+            Some((_, end)) if *end + 1 == index => *end = index,
+            _ => ranges.push((index, index)),
+        }
+    }
+
+    if ranges.is_empty() {
+        return "none".to_owned();
+    }
+
+    ranges
+        .into_iter()
+        .map(|(start, end)| match start == end {
+            true => start.to_string(),
+            false => format!("{}-{}", start, end),
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
 /// Wrapper function around fetch_and_scribe to put a limit on the number of tweets that can be in
-/// memory at once. Each batch is requested, parsed and stored in series. All of the tweet ids within
-/// a given batch will be requested, parsed and stored concurrently.
+/// memory at once. The batches are split into groups of at most `batch_size` tweets, and each group
+/// is requested, parsed and stored in series. All of the batches within a group will be requested,
+/// parsed and stored concurrently. A group is still started if batches in earlier groups failed.
 async fn batched_fetch_and_scribe(
     client: Arc<goldcrest::Client>,
     db_pool: &PgPool,
-    tweet_ids: &[u64],
+    job_id: i32,
+    batches: &[FetchBatch],
     batch_size: usize,
-    verbose: bool
-) -> Result<Vec<IdentBuf>, ScribeFailure>
+    overrides: Arc<ParseOverrides>,
+    scribe_opts: ScribeOptions
+) -> FetchResults
 {
-    let mut group_ids = Vec::new();
-    let num_tweets = tweet_ids.len();
-    let mut min_tweet_index = 0usize;
+    let mut results = FetchResults::default();
+    let mut remaining = batches;
 
-    while min_tweet_index < num_tweets {
-        let max_tweet_index = (min_tweet_index + batch_size).min(num_tweets);
-        let current_batch = &tweet_ids[min_tweet_index..max_tweet_index];
+    while !remaining.is_empty() {
+        // Always take at least one batch, in case the job was created with a larger batch size
+        let mut n_batches = 1;
+        let mut n_tweets = remaining[0].tweet_ids.len();
+        while n_batches < remaining.len() && n_tweets + remaining[n_batches].tweet_ids.len() <= batch_size {
+            n_tweets += remaining[n_batches].tweet_ids.len();
+            n_batches += 1;
+        }
 
-        group_ids.extend(
-            fetch_and_scribe(client.clone(), db_pool, current_batch, verbose)
-                .await?
-                .into_iter());
+        let (current_batches, rest) = remaining.split_at(n_batches);
 
-        min_tweet_index = max_tweet_index;
+        results.extend(
+            fetch_and_scribe(client.clone(), db_pool, job_id, current_batches, overrides.clone(), scribe_opts)
+                .await);
+
+        remaining = rest;
     }
 
-    Ok(group_ids)
+    results
 }
 
-/// Splits the given tweet ids into groups of 100, then concurrently requests each group of 100,
-/// parses the received tweets and adds them to the database.
+/// Concurrently requests each of the given batches of the fetch job, parses the received tweets
+/// and adds them to the database. Each batch is marked as completed once its tweets have been
+/// stored, or has its error recorded if it fails. Every batch is waited for, even if others fail.
 async fn fetch_and_scribe(
     client: Arc<goldcrest::Client>,
     db_pool: &PgPool,
-    tweet_ids: &[u64],
-    verbose: bool
-) -> Result<Vec<IdentBuf>, ScribeFailure>
+    job_id: i32,
+    batches: &[FetchBatch],
+    overrides: Arc<ParseOverrides>,
+    scribe_opts: ScribeOptions
+) -> FetchResults
 {
-    const TWEETS_PER_REQUEST: usize = 100;
-
-    let mut tweet_ids = tweet_ids.to_vec();
-    tweet_ids.sort_unstable();
-    tweet_ids.dedup();
-    let n_tweet_ids = tweet_ids.len();
-
     let mut join_handles = Vec::new();
-    
-    let mut assigned: usize = 0;
-    while assigned < n_tweet_ids {
-        let max_id = (assigned + TWEETS_PER_REQUEST).min(n_tweet_ids);
-        let ids = (&tweet_ids[assigned..max_id]).to_vec();
 
+    for batch in batches {
+        let batch = batch.clone();
         let client = client.clone();
+        let overrides = overrides.clone();
         // Clone the pool because it's just a wrapper around an Arc
         let db_pool = db_pool.clone();
 
         join_handles.push(tokio::spawn(async move {
-            let tweets_res = client
-                .get_tweets(ids, TweetOptions::default())
-                .await;
+            let res = fetch_and_scribe_batch(&client, &db_pool, &batch, &overrides, scribe_opts).await;
 
-            match tweets_res {
-                Err(err) => Err(err.into()),
+            let mark_res = match &res {
+                Ok(_) => sqlx::query(
+                    "UPDATE fetch_job_batches SET completed_at = now(), error = NULL \
+                    WHERE job_id = $1 AND batch = $2"
+                )
+                .bind(job_id)
+                .bind(batch.index)
+                .execute(&db_pool)
+                .await,
 
-                Ok(tweets) => {
-                    match db_pool.acquire().await {
-                        Err(err) => Err(err.into()),
+                Err(failure) => sqlx::query(
+                    "UPDATE fetch_job_batches SET error = $3 WHERE job_id = $1 AND batch = $2"
+                )
+                .bind(job_id)
+                .bind(batch.index)
+                .bind(failure.error.to_string())
+                .execute(&db_pool)
+                .await,
+            };
 
-                        Ok(mut pool_conn) =>
-                            scribe::scribe_tweets(&mut pool_conn, &tweets, verbose).await,
-                    }
-                },
+            // The batch's tweets have been stored even if it could not be marked as completed, so
+            // its results are kept; resuming the job will just request the batch again
+            if let Err(err) = mark_res {
+                eprintln!("fetch job {}: failed to record the outcome of batch {}: {}", job_id, batch.index, err);
             }
+
+            res
         }));
+    }
+
+    let mut results = FetchResults::default();
+    for (batch, join_handle) in batches.iter().zip(join_handles) {
+        let res = match join_handle.await {
+            Ok(res) => res,
+            Err(err) => Err(BatchFailure {
+                scribed: Vec::new(),
+                remaining: batch.tweet_ids.clone(),
+                error: err.into(),
+            }),
+        };
+
+        match res {
+            Ok(batch_result) => {
+                results.scribed.extend(batch_result.scribed);
+                results.unavailable.extend(batch_result.unavailable);
+            },
+            Err(failure) => {
+                results.scribed.extend(failure.scribed);
+                results.failed.push(FailedBatch {
+                    batch: batch.clone(),
+                    remaining: failure.remaining,
+                    error: failure.error,
+                });
+            },
+        }
+    }
+
+    results
+}
+
+/// Requests the tweets of the batch, then parses and stores them. If an error occurs after some of
+/// the tweets have been stored, their results are returned along with the error.
+async fn fetch_and_scribe_batch(
+    client: &goldcrest::Client,
+    db_pool: &PgPool,
+    batch: &FetchBatch,
+    overrides: &ParseOverrides,
+    scribe_opts: ScribeOptions
+) -> Result<BatchResult, BatchFailure>
+{
+    let tweets = match client.get_tweets(batch.tweet_ids.clone(), TweetOptions::default()).await {
+        Ok(tweets) => tweets,
+        Err(err) => return Err(BatchFailure {
+            scribed: Vec::new(),
+            remaining: batch.tweet_ids.clone(),
+            error: err.into(),
+        }),
+    };
 
-        assigned = max_id;
+    let received = tweets
+        .iter()
+        .map(|tweet| tweet.id)
+        .collect::<HashSet<_>>();
+
+    let unavailable = batch.tweet_ids
+        .iter()
+        .copied()
+        .filter(|id| !received.contains(id))
+        .collect::<Vec<_>>();
+
+    if scribe_opts.verbose {
+        for id in &unavailable {
+            eprintln!("skip tweet {}: tweet was not returned", id);
+        }
     }
 
-    let mut group_ids = Vec::new();
-    for join_handle in join_handles {
-        group_ids.extend(join_handle.await??.into_iter());
+    let posts = tweets
+        .iter()
+        .map(RobotPost::from_tweet)
+        .collect::<Vec<_>>();
+
+    let mut scribed = Vec::with_capacity(posts.len());
+
+    let res: Result<(), ScribeFailure> = async {
+        let mut pool_conn = db_pool.acquire().await?;
+
+        scribe::scribe_posts_into(&mut pool_conn, &posts, overrides, scribe_opts, &mut scribed).await?;
+
+        // Record the missing tweets so that they are not requested again by later fetches, and
+        // forget any tweets which have become available again
+        sqlx::query(
+            "INSERT INTO unavailable_tweets (tweet_id) SELECT * FROM UNNEST($1) \
+            ON CONFLICT (tweet_id) DO UPDATE SET last_seen_at = now()"
+        )
+        .bind(unavailable.iter().map(|&id| id as i64).collect::<Vec<_>>())
+        .execute(&mut pool_conn)
+        .await?;
+
+        sqlx::query("DELETE FROM unavailable_tweets WHERE tweet_id = ANY($1)")
+            .bind(received.iter().map(|&id| id as i64).collect::<Vec<_>>())
+            .execute(&mut pool_conn)
+            .await?;
+
+        Ok(())
     }
+    .await;
 
-    Ok(group_ids)
+    match res {
+        Ok(()) => Ok(BatchResult {
+            scribed,
+            unavailable,
+        }),
+
+        Err(error) => {
+            // The results are in the same order as the tweets, so the tweets without a result are
+            // the ones which were not stored. The unavailable tweets are also left to be requested
+            // again, since they may not have been recorded.
+            let stored = tweets[..scribed.len()]
+                .iter()
+                .map(|tweet| tweet.id)
+                .collect::<HashSet<_>>();
+
+            let remaining = batch.tweet_ids
+                .iter()
+                .copied()
+                .filter(|id| !stored.contains(id))
+                .collect();
+
+            Err(BatchFailure { scribed, remaining, error })
+        },
+    }
 }
@@ -0,0 +1,91 @@
+use std::ops::Range;
+
+use unicode_normalization::char::{decompose_compatible, is_combining_mark};
+
+/// A copy of a string with stylised characters replaced by plain ones, which keeps track of where
+/// each of its characters came from in the original string. This allows regexes to be matched
+/// against the folded string while still being able to return slices of the original.
+///
+/// Folding applies compatibility decomposition (so fullwidth and mathematical letters become
+/// their plain equivalents), removes combining marks such as accents, removes zero-width
+/// characters and replaces common homoglyphs from other scripts with ASCII lookalikes.
+pub struct Folded<'a> {
+    original: &'a str,
+    folded: String,
+    /// For each byte of `folded`, the byte range of the character in `original` it came from.
+    spans: Vec<(usize, usize)>,
+}
+
+impl<'a> Folded<'a> {
+    pub fn new(original: &'a str) -> Self {
+        let mut folded = String::with_capacity(original.len());
+        let mut spans = Vec::with_capacity(original.len());
+
+        for (i, c) in original.char_indices() {
+            if is_zero_width(c) {
+                continue;
+            }
+
+            let span = (i, i + c.len_utf8());
+
+            decompose_compatible(c, |d| {
+                if !is_combining_mark(d) {
+                    let d = fold_confusable(d);
+                    folded.push(d);
+                    spans.extend((0..d.len_utf8()).map(|_| span));
+                }
+            });
+        }
+
+        Self {
+            original,
+            folded,
+            spans,
+        }
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.folded
+    }
+
+    /// Converts a byte range of the folded string to the byte range of the original string that it
+    /// came from.
+    pub fn original_range(&self, range: Range<usize>) -> Range<usize> {
+        let start = self.spans
+            .get(range.start)
+            .map_or(self.original.len(), |span| span.0);
+
+        let end = match range.end {
+            end if end <= range.start => start,
+            end => self.spans[end - 1].1,
+        };
+
+        start..end
+    }
+
+    /// Returns the slice of the original string that the given byte range of the folded string came
+    /// from.
+    pub fn original_slice(&self, range: Range<usize>) -> &'a str {
+        &self.original[self.original_range(range)]
+    }
+}
+
+fn is_zero_width(c: char) -> bool {
+    matches!(c, '\u{00ad}' | '\u{200b}' ..= '\u{200d}' | '\u{2060}' | '\u{feff}')
+}
+
+/// Replaces letters from other scripts which look like Latin letters with the Latin letter.
+fn fold_confusable(c: char) -> char {
+    match c {
+        // Cyrillic
+        'А' => 'A', 'В' => 'B', 'Е' => 'E', 'К' => 'K', 'М' => 'M', 'Н' => 'H', 'О' => 'O',
+        'Р' => 'P', 'С' => 'C', 'Ѕ' => 'S', 'Т' => 'T', 'Х' => 'X', 'Ь' => 'b',
+        'а' => 'a', 'е' => 'e', 'о' => 'o', 'р' => 'p', 'с' => 'c', 'ѕ' => 's', 'у' => 'y',
+        'х' => 'x', 'ь' => 'b',
+        // Greek
+        'Α' => 'A', 'Β' => 'B', 'Ε' => 'E', 'Ζ' => 'Z', 'Η' => 'H', 'Ι' => 'I', 'Κ' => 'K',
+        'Μ' => 'M', 'Ν' => 'N', 'Ο' => 'O', 'Ρ' => 'P', 'Τ' => 'T', 'Υ' => 'Y', 'Χ' => 'X',
+        'ο' => 'o', 'τ' => 't',
+        c => c,
+    }
+}
@@ -1 +1,93 @@
+use anyhow::{anyhow, Context};
+use sqlx::postgres::PgConnection;
 
+use crate::model::IdentBuf;
+use crate::parse;
+
+/// A way of referring to a robot given by a user: either a robot id or alias such as `"558/salt"`,
+/// or a bare robot number such as `"558"`.
+enum RobotRef {
+    Ident(IdentBuf),
+    Number(i32),
+}
+
+impl RobotRef {
+    fn parse(s: &str) -> anyhow::Result<Self> {
+        let s = s.trim();
+
+        if s.contains('/') {
+            parse_ident(s).map(Self::Ident)
+        } else {
+            s.parse::<i32>()
+                .map(Self::Number)
+                .map_err(|_| anyhow!(r#"invalid robot id "{}""#, s))
+        }
+    }
+}
+
+/// Parses a robot id or alias of the form `number/name`, normalising the name in the same way as the
+/// parser so that e.g. `"558/Salt"` gives `558/salt`.
+pub(crate) fn parse_ident(s: &str) -> anyhow::Result<IdentBuf> {
+    let s = s.trim();
+
+    let id = s.parse::<IdentBuf>()
+        .map_err(|err| anyhow!(r#"invalid robot id "{}": {}"#, s, err))?;
+
+    let name = parse::normalise_ident(&id.name);
+
+    if name.is_empty() {
+        return Err(anyhow!(r#"invalid robot id "{}": name contains no letters or digits"#, s));
+    }
+
+    Ok(IdentBuf::new(id.number, name))
+}
+
+/// Resolves a robot id, alias or bare robot number to the id of the robot it refers to. A bare
+/// number is only accepted if exactly one robot has that number.
+pub(crate) async fn resolve(db_conn: &mut PgConnection, robot_ref: &str) -> anyhow::Result<IdentBuf> {
+    match RobotRef::parse(robot_ref)? {
+        RobotRef::Ident(id) => {
+            let resolved: Option<IdentBuf> = sqlx::query_scalar(
+                "SELECT id FROM robots WHERE id = $1 \
+                UNION ALL \
+                SELECT robot_id FROM robot_aliases WHERE alias = $1 \
+                LIMIT 1"
+            )
+            .bind(&id)
+            .fetch_optional(&mut *db_conn)
+            .await
+            .with_context(|| format!("failed to look up robot {}", id))?;
+
+            resolved.ok_or_else(|| anyhow!("no robot or alias {}", id))
+        },
+
+        RobotRef::Number(number) => {
+            let mut resolved: Vec<IdentBuf> = sqlx::query_scalar(
+                "SELECT id FROM robots WHERE (id).number = $1 LIMIT 2"
+            )
+            .bind(number)
+            .fetch_all(&mut *db_conn)
+            .await
+            .with_context(|| format!("failed to look up robot number {}", number))?;
+
+            match resolved.len() {
+                0 => Err(anyhow!("no robot numbered {}", number)),
+                1 => Ok(resolved.remove(0)),
+                _ => Err(anyhow!("more than one robot is numbered {}; give its full id instead", number)),
+            }
+        },
+    }
+}
+
+/// Resolves each of the given robot ids, aliases or bare robot numbers to the id of the robot it
+/// refers to, in order.
+pub(crate) async fn resolve_all<S>(db_conn: &mut PgConnection, robot_refs: &[S]) -> anyhow::Result<Vec<IdentBuf>>
+where
+    S: AsRef<str>,
+{
+    let mut ids = Vec::with_capacity(robot_refs.len());
+    for robot_ref in robot_refs {
+        ids.push(resolve(&mut *db_conn, robot_ref.as_ref()).await?);
+    }
+    Ok(ids)
+}
@@ -19,6 +19,7 @@ use tokio::io::AsyncReadExt;
 use tokio::sync::Semaphore;
 use url::Url;
 
+use crate::ident;
 use crate::model::{RobotImageUrl, RobotImagePath, RobotImagePathOpt, IdentBuf};
 
 #[derive(Parser, Debug)]
@@ -75,7 +76,7 @@ pub(crate) async fn run(db_pool: &PgPool, opts: Opts) -> anyhow::Result<()> {
 
                 match opts.subcommand {
                     Subcommand::Ids => {
-                        let robot_ids = read_stdin_ids()
+                        let robot_ids = read_stdin_ids(&mut db_conn)
                             .await
                             .context("failed to read robot ids from stdin")?;
 
@@ -131,7 +132,7 @@ pub(crate) async fn run(db_pool: &PgPool, opts: Opts) -> anyhow::Result<()> {
             
             let opt_robots = match opts.subcommand {
                 Subcommand::Ids => {
-                    let robot_ids = read_stdin_ids()
+                    let robot_ids = read_stdin_ids(&mut db_conn)
                         .await
                         .context("failed to read robot ids from stdin")?;
 
@@ -150,8 +151,11 @@ pub(crate) async fn run(db_pool: &PgPool, opts: Opts) -> anyhow::Result<()> {
             for opt_robot in opt_robots {
                 match opt_robot.image_path {
                     Some(image_path) => robots.push(RobotImagePath {
+                        group_id: opt_robot.group_id,
                         id: opt_robot.id,
+                        position: opt_robot.position,
                         image_path,
+                        video_path: opt_robot.video_path,
                     }),
 
                     None => {
@@ -188,61 +192,90 @@ pub(crate) async fn run(db_pool: &PgPool, opts: Opts) -> anyhow::Result<()> {
     }
 }
 
-// Reads a list of robot robot ids from stdin.
-async fn read_stdin_ids() -> anyhow::Result<Vec<IdentBuf>> {
+// Reads a list of robot ids, aliases or numbers from stdin and resolves them to robot ids.
+async fn read_stdin_ids(db_conn: &mut PgConnection) -> anyhow::Result<Vec<IdentBuf>> {
     let mut buffer = String::new();
     tokio::io::stdin()
         .read_to_string(&mut buffer)
         .await?;
-    
-    buffer
-        .split_whitespace()
-        .map(|id| id
-            .parse::<IdentBuf>()
-            .map_err(|_| anyhow!("invalid robot robot id \"{}\"", id)))
-        .collect::<Result<Vec<_>, _>>()
+
+    ident::resolve_all(db_conn, &buffer.split_whitespace().collect::<Vec<_>>())
+        .await
 }
 
-/// Get the image urls of all of the robots with the given ids.
+/// Selects the url of the highest-bitrate mp4 variant of the robot media `m`.
+const BEST_VIDEO_URL_SQL: &str =
+    "(SELECT v.url FROM robot_media_variants v \
+        WHERE v.group_id = m.group_id AND v.position = m.position AND v.content_type = 'video/mp4' \
+        ORDER BY v.bitrate DESC NULLS LAST LIMIT 1)";
+
+/// Selects the id of the first robot of the group of the robot media `m`, which is used to refer
+/// to the group in file names and messages.
+const FIRST_ROBOT_ID_SQL: &str =
+    "(SELECT r.id FROM robots r WHERE r.group_id = m.group_id ORDER BY r.id LIMIT 1)";
+
+/// Get the image and video urls of all of the media of the groups of the robots with the given ids.
+/// The media of each group is only returned once, even if several of its robots are given.
 async fn get_image_urls(
     db_conn: &mut PgConnection,
     robot_ids: &[IdentBuf]
 ) -> sqlx::Result<Vec<RobotImageUrl>>
 {
-    sqlx::query_as("SELECT id, image_url FROM robots WHERE id = ANY($1)")
+    sqlx::query_as(&format!(
+        "SELECT m.group_id, {} AS id, m.position, m.url AS image_url, {} AS video_url FROM robot_media m \
+        WHERE m.group_id IN (SELECT group_id FROM robots WHERE id = ANY($1))",
+        FIRST_ROBOT_ID_SQL, BEST_VIDEO_URL_SQL
+    ))
         .bind(robot_ids)
         .fetch_all(db_conn)
         .await
 }
 
-/// Get the image urls of all of the robots which have no image path in the database.
+/// Get the image and video urls of all of the robot media which have no image path in the
+/// database, or which have a video that has not been downloaded.
 async fn get_image_urls_missing(
     db_conn: &mut PgConnection
 ) -> sqlx::Result<Vec<RobotImageUrl>>
 {
-    sqlx::query_as("SELECT id, image_url FROM robots WHERE image_path IS NULL")
+    sqlx::query_as(&format!(
+        "SELECT * FROM ( \
+            SELECT m.group_id, {} AS id, m.position, m.url AS image_url, {} AS video_url, \
+                m.image_path, m.video_path \
+            FROM robot_media m \
+        ) AS media \
+        WHERE image_path IS NULL OR (video_path IS NULL AND video_url IS NOT NULL)",
+        FIRST_ROBOT_ID_SQL, BEST_VIDEO_URL_SQL
+    ))
         .fetch_all(db_conn)
         .await
 }
 
-/// Get the image paths of all of the robots with the given ids.
+/// Get the image paths of all of the media of the groups of the robots with the given ids.
 async fn get_image_paths(
     db_conn: &mut PgConnection,
     robot_ids: &[IdentBuf]
 ) -> sqlx::Result<Vec<RobotImagePathOpt>>
 {
-    sqlx::query_as("SELECT id, image_path FROM robots WHERE id = ANY($1)")
+    sqlx::query_as(&format!(
+        "SELECT m.group_id, {} AS id, m.position, m.image_path, m.video_path FROM robot_media m \
+        WHERE m.group_id IN (SELECT group_id FROM robots WHERE id = ANY($1))",
+        FIRST_ROBOT_ID_SQL
+    ))
         .bind(robot_ids)
         .fetch_all(db_conn)
         .await
 }
 
-/// Get the image paths of all of the robots which have no image thumb path in the database.
+/// Get the image paths of all of the robot media which have no image thumb path in the database.
 async fn get_image_paths_missing(
     db_conn: &mut PgConnection
 ) -> sqlx::Result<Vec<RobotImagePathOpt>>
 {
-    sqlx::query_as("SELECT id, image_path FROM robots WHERE image_thumb_path IS NULL")
+    sqlx::query_as(&format!(
+        "SELECT m.group_id, {} AS id, m.position, m.image_path, m.video_path FROM robot_media m \
+        WHERE m.image_thumb_path IS NULL",
+        FIRST_ROBOT_ID_SQL
+    ))
         .fetch_all(db_conn)
         .await
 }
@@ -280,9 +313,12 @@ async fn get_images(
                         limiter.until_ready().await;
                         download_and_store(&db_pool, &http_client, &robot, dir.as_deref(), &file_name)
                             .await
-                            .map(move |_| RobotImagePath {
+                            .map(move |video_path| RobotImagePath {
+                                group_id: robot.group_id,
                                 id: robot.id,
+                                position: robot.position,
                                 image_path: file_name.to_owned(),
+                                video_path,
                             })
                     },
 
@@ -305,60 +341,118 @@ async fn get_images(
     results
 }
 
+/// Downloads the image of the robot media and, if it is an animated gif or video, its best mp4
+/// encoding. Returns the file name of the downloaded video, if any.
 async fn download_and_store<P>(
     db_pool: &PgPool,
     http_client: &reqwest::Client,
     robot: &RobotImageUrl,
     dir: Option<P>,
     file_name: &str,
-) -> Result<(), ImgError>
+) -> Result<Option<String>, ImgError>
 where
     P: AsRef<Path>
 {
-    match dir.as_ref() {
+    let dimensions = match dir.as_ref() {
         Some(dir) =>
             download_image(http_client, robot, dir.as_ref().join(file_name)).await,
         None =>
             download_image(http_client, robot, file_name).await,
     }?;
+
+    let video_file_name = match robot.video_url {
+        Some(ref video_url) => {
+            let video_file_name = gen_image_file_name("video", &robot.id, "mp4")
+                .map_err(|err| ImgError::new(robot.id.clone(), ImgErrorCause::GenFileNameError(Box::new(err))))?;
+
+            match dir.as_ref() {
+                Some(dir) =>
+                    download_video(http_client, robot, video_url, dir.as_ref().join(&video_file_name)).await,
+                None =>
+                    download_video(http_client, robot, video_url, &video_file_name).await,
+            }?;
+
+            Some(video_file_name)
+        },
+
+        None => None,
+    };
     
     let mut db_conn = db_pool
         .acquire()
         .await
         .map_err(|err| ImgError::new(robot.id.clone(), err.into()))?;
 
-    store_image_path(&mut db_conn, robot, file_name).await
+    store_image_path(&mut db_conn, robot, file_name, video_file_name.as_deref(), dimensions).await?;
+
+    Ok(video_file_name)
 }
 
+/// Downloads the image and writes it to the given path, returning the width and height of the image
+/// if they could be determined.
 async fn download_image<P>(
     http_client: &reqwest::Client,
     robot: &RobotImageUrl,
     path: P,
-) -> Result<(), ImgError>
+) -> Result<Option<(u32, u32)>, ImgError>
 where
     P: AsRef<Path>
 {
     let image_url = image_large_png_url(&robot.image_url)
         .map_err(|err| ImgError::new(robot.id.clone(), err.into()))?;
 
-    let resp = http_client.get(image_url)
-        .send()
+    let image_data = download_bytes(http_client, &robot.id, image_url).await?;
+
+    let dimensions = image::io::Reader::new(io::Cursor::new(&image_data))
+        .with_guessed_format()
+        .ok()
+        .and_then(|reader| reader.into_dimensions().ok());
+
+    tokio::fs::write(path, &image_data)
         .await
         .map_err(|err| ImgError::new(robot.id.clone(), err.into()))?;
 
+    Ok(dimensions)
+}
+
+async fn download_video<P>(
+    http_client: &reqwest::Client,
+    robot: &RobotImageUrl,
+    video_url: &str,
+    path: P,
+) -> Result<(), ImgError>
+where
+    P: AsRef<Path>
+{
+    let video_url = Url::parse(video_url)
+        .map_err(|err| ImgError::new(robot.id.clone(), err.into()))?;
+
+    let video_data = download_bytes(http_client, &robot.id, video_url).await?;
+
+    tokio::fs::write(path, &video_data)
+        .await
+        .map_err(|err| ImgError::new(robot.id.clone(), err.into()))
+}
+
+async fn download_bytes(
+    http_client: &reqwest::Client,
+    robot_id: &IdentBuf,
+    url: Url,
+) -> Result<Vec<u8>, ImgError>
+{
+    let resp = http_client.get(url)
+        .send()
+        .await
+        .map_err(|err| ImgError::new(robot_id.clone(), err.into()))?;
+
     match resp.status() {
-        status if status.is_success() => {
-            let image_data = resp
-                .bytes()
-                .await
-                .map_err(|err| ImgError::new(robot.id.clone(), err.into()))?;
-
-            tokio::fs::write(path, &image_data)
-                .await
-                .map_err(|err| ImgError::new(robot.id.clone(), err.into()))
-        },
+        status if status.is_success() => resp
+            .bytes()
+            .await
+            .map(|data| data.to_vec())
+            .map_err(|err| ImgError::new(robot_id.clone(), err.into())),
 
-        status => Err(ImgError::new(robot.id.clone(), ImgErrorCause::HttpError(status))),
+        status => Err(ImgError::new(robot_id.clone(), ImgErrorCause::HttpError(status))),
     }
 }
 
@@ -366,21 +460,40 @@ async fn store_image_path(
     db_conn: &mut PgConnection,
     robot: &RobotImageUrl,
     file_name: &str,
+    video_file_name: Option<&str>,
+    dimensions: Option<(u32, u32)>,
 ) -> Result<(), ImgError>
 {
-    let rows_affected = sqlx::query("UPDATE robots SET image_path = $1 WHERE id = $2")
-        .bind(file_name)
-        .bind(&robot.id)
-        .execute(db_conn)
-        .await
-        .map_err(|err| ImgError::new(robot.id.clone(), err.into()))?
-        .rows_affected();
+    let rows_affected = sqlx::query(
+        "UPDATE robot_media SET image_path = $1, video_path = $2, width = $3, height = $4 \
+        WHERE group_id = $5 AND position = $6"
+    )
+    .bind(file_name)
+    .bind(video_file_name)
+    .bind(dimensions.map(|(width, _)| width as i32))
+    .bind(dimensions.map(|(_, height)| height as i32))
+    .bind(robot.group_id)
+    .bind(robot.position)
+    .execute(&mut *db_conn)
+    .await
+    .map_err(|err| ImgError::new(robot.id.clone(), err.into()))?
+    .rows_affected();
 
     if rows_affected < 1 {
-        Err(ImgError::new(robot.id.clone(), ImgErrorCause::NoRowsUpdated))
-    } else {
-        Ok(())
+        return Err(ImgError::new(robot.id.clone(), ImgErrorCause::NoRowsUpdated));
     }
+
+    // The first media item is the group's primary image
+    if robot.position == 0 {
+        sqlx::query("UPDATE robot_groups SET image_path = $1 WHERE id = $2")
+            .bind(file_name)
+            .bind(robot.group_id)
+            .execute(&mut *db_conn)
+            .await
+            .map_err(|err| ImgError::new(robot.id.clone(), err.into()))?;
+    }
+
+    Ok(())
 }
 
 async fn gen_thumbs(
@@ -448,16 +561,44 @@ async fn gen_thumb<P>(
 where
     P: AsRef<Path>
 {
-    let original = {
-        let image_data = match dir.as_ref(){
-            Some(dir) => tokio::fs::read(dir.as_ref().join(&robot.image_path)).await,
-            None => tokio::fs::read(&robot.image_path).await,
-        }.map_err(|err| ImgError::new(robot.id.clone(), err.into()))?;
-
-        match ImageFormat::from_path(&robot.image_path).ok() {
-            Some(image_format) => image::load_from_memory_with_format(&image_data, image_format),
-            None => image::load_from_memory(&image_data),
-        }.map_err(|err| ImgError::new(robot.id.clone(), err.into()))?
+    // For animated gifs and videos, generate the thumb from the first frame rather than the poster
+    // frame where possible
+    let first_frame = match robot.video_path {
+        Some(ref video_path) => {
+            let video_path = match dir.as_ref() {
+                Some(dir) => dir.as_ref().join(video_path),
+                None => PathBuf::from(video_path),
+            };
+
+            match video_first_frame(&video_path).await {
+                Ok(frame) => Some(frame),
+                Err(err) => {
+                    eprintln!(
+                        "robot {}: failed to read first frame of video, using poster frame instead: {}",
+                        robot.id, err
+                    );
+                    None
+                },
+            }
+        },
+
+        None => None,
+    };
+
+    let original = match first_frame {
+        Some(frame) => frame,
+
+        None => {
+            let image_data = match dir.as_ref(){
+                Some(dir) => tokio::fs::read(dir.as_ref().join(&robot.image_path)).await,
+                None => tokio::fs::read(&robot.image_path).await,
+            }.map_err(|err| ImgError::new(robot.id.clone(), err.into()))?;
+
+            match ImageFormat::from_path(&robot.image_path).ok() {
+                Some(image_format) => image::load_from_memory_with_format(&image_data, image_format),
+                None => image::load_from_memory(&image_data),
+            }.map_err(|err| ImgError::new(robot.id.clone(), err.into()))?
+        },
     };
 
     let thumb = original.resize_to_fill(size, size, FilterType::Lanczos3);
@@ -485,19 +626,48 @@ where
         .await
         .map_err(|err| ImgError::new(robot.id.clone(), err.into()))?;
 
-    let rows_affected = sqlx::query("UPDATE robots SET image_thumb_path = $1 WHERE id = $2")
-        .bind(file_name)
-        .bind(&robot.id)
-        .execute(&mut db_conn)
-        .await
-        .map_err(|err| ImgError::new(robot.id.clone(), err.into()))?
-        .rows_affected();
+    let rows_affected = sqlx::query(
+        "UPDATE robot_media SET image_thumb_path = $1 \
+        WHERE group_id = $2 AND position = $3"
+    )
+    .bind(file_name)
+    .bind(robot.group_id)
+    .bind(robot.position)
+    .execute(&mut db_conn)
+    .await
+    .map_err(|err| ImgError::new(robot.id.clone(), err.into()))?
+    .rows_affected();
 
     if rows_affected < 1 {
-        Err(ImgError::new(robot.id.clone(), ImgErrorCause::NoRowsUpdated))
-    } else {
-        Ok(())
+        return Err(ImgError::new(robot.id.clone(), ImgErrorCause::NoRowsUpdated));
+    }
+
+    if robot.position == 0 {
+        sqlx::query("UPDATE robot_groups SET image_thumb_path = $1 WHERE id = $2")
+            .bind(file_name)
+            .bind(robot.group_id)
+            .execute(&mut db_conn)
+            .await
+            .map_err(|err| ImgError::new(robot.id.clone(), err.into()))?;
+    }
+
+    Ok(())
+}
+
+/// Decodes the first frame of a video using ffmpeg.
+async fn video_first_frame(path: &Path) -> Result<DynamicImage, ImgErrorCause> {
+    let output = tokio::process::Command::new("ffmpeg")
+        .args(["-v", "error", "-i"])
+        .arg(path)
+        .args(["-frames:v", "1", "-f", "image2pipe", "-c:v", "png", "-"])
+        .output()
+        .await?;
+
+    if !output.status.success() {
+        return Err(ImgErrorCause::FfmpegError(String::from_utf8_lossy(&output.stderr).trim().to_owned()));
     }
+
+    Ok(image::load_from_memory_with_format(&output.stdout, ImageFormat::Png)?)
 }
 
 fn is_approx_grayscale(image: &DynamicImage, threshold: f32) -> bool {
@@ -591,6 +761,7 @@ enum ImgErrorCause {
     HttpError(StatusCode),
     NoRowsUpdated,
     GenFileNameError(Box<fmt::Error>),
+    FfmpegError(String),
 }
 
 impl ImgError {
@@ -621,6 +792,7 @@ impl fmt::Display for ImgErrorCause {
             Self::HttpError(status) => status.fmt(f),
             Self::NoRowsUpdated => write!(f, "no rows affected by update"),
             Self::GenFileNameError(err) => write!(f, "error generating file name: {}", err),
+            Self::FfmpegError(msg) => write!(f, "ffmpeg failed: {}", msg),
         }
     }
 }
@@ -0,0 +1,1081 @@
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Context};
+use chrono::{DateTime, Utc};
+use clap::Parser;
+use serde::Deserialize;
+use serde_json::Value;
+use sqlx::postgres::PgPool;
+
+use crate::model::{Platform, RawMedia, RawVideoInfo, RawVideoVariant};
+use crate::overrides::ParseOverrides;
+use crate::scribe::{self, PostMention, PostSource, PostUrl, RobotPost, ScribeOptions};
+
+#[derive(Parser, Debug)]
+pub(crate) struct Opts {
+    #[clap(subcommand)]
+    subcommand: Subcommand,
+}
+
+#[derive(Parser, Debug)]
+enum Subcommand {
+    /// Read robot Tweets from the tweets.js files of a Twitter data download, without using the
+    /// Twitter API.
+    TwitterArchive(TwitterArchiveOpts),
+
+    /// Read robot posts from an ActivityPub outbox, such as that of a Mastodon account.
+    #[clap(name = "activitypub")]
+    ActivityPub(ActivityPubOpts),
+}
+
+#[derive(Parser, Debug)]
+struct TwitterArchiveOpts {
+    /// Display additional information.
+    #[clap(short, long)]
+    verbose: bool,
+
+    /// Skip robot Tweets which parsed with warnings, rather than storing them flagged for review.
+    #[clap(long)]
+    strict: bool,
+
+    /// Update robots which have already been stored, rather than skipping their Tweets.
+    #[clap(long)]
+    update: bool,
+
+    /// A json file of manual corrections for robot Tweets which cannot be parsed correctly.
+    #[clap(long)]
+    overrides: Option<PathBuf>,
+
+    /// The extracted archive, its data directory, or a single tweets.js file.
+    path: PathBuf,
+}
+
+#[derive(Parser, Debug)]
+struct ActivityPubOpts {
+    /// Display additional information.
+    #[clap(short, long)]
+    verbose: bool,
+
+    /// Skip robot posts which parsed with warnings, rather than storing them flagged for review.
+    #[clap(long)]
+    strict: bool,
+
+    /// Update robots which have already been stored, rather than skipping their posts.
+    #[clap(long)]
+    update: bool,
+
+    /// The outbox to read, either a json file or an http(s) url. Pages of the outbox are only
+    /// requested when reading from a url; a file must contain its posts inline.
+    outbox: String,
+}
+
+pub(crate) async fn run(db_pool: &PgPool, opts: Opts) -> anyhow::Result<()> {
+    match opts.subcommand {
+        Subcommand::TwitterArchive(opts) => import_twitter_archive(db_pool, opts).await,
+        Subcommand::ActivityPub(opts) => import_activitypub(db_pool, opts).await,
+    }
+}
+
+async fn import_twitter_archive(db_pool: &PgPool, opts: TwitterArchiveOpts) -> anyhow::Result<()> {
+    let files = archive_tweet_files(&opts.path).await?;
+
+    let mut posts = Vec::new();
+
+    for file in &files {
+        let contents = tokio::fs::read_to_string(file)
+            .await
+            .with_context(|| format!("failed to read archive file {}", file.to_string_lossy()))?;
+
+        let entries = parse_archive_file(&contents)
+            .with_context(|| format!("invalid archive file {}", file.to_string_lossy()))?;
+
+        for entry in entries {
+            let tweet_id = entry.tweet.id_str.clone();
+
+            let post = entry.tweet
+                .into_post()
+                .with_context(|| format!("invalid tweet {} in {}", tweet_id, file.to_string_lossy()))?;
+
+            if let Some(post) = post {
+                posts.push(post);
+            }
+        }
+    }
+
+    eprintln!("{} tweets read from {} archive files", posts.len(), files.len());
+
+    let overrides = ParseOverrides::load(opts.overrides.as_deref()).await?;
+
+    let scribe_opts = ScribeOptions {
+        verbose: opts.verbose,
+        strict: opts.strict,
+        update: opts.update,
+    };
+
+    store_posts(db_pool, Platform::Twitter, posts, &overrides, scribe_opts).await
+}
+
+async fn import_activitypub(db_pool: &PgPool, opts: ActivityPubOpts) -> anyhow::Result<()> {
+    let items = outbox_items(&opts.outbox).await?;
+
+    let mut posts = Vec::new();
+
+    for item in &items {
+        let note = match outbox_note(item) {
+            Some(note) => note,
+            None => {
+                if opts.verbose {
+                    eprintln!("skip outbox item {}: not a note", item_id(item));
+                }
+                continue;
+            },
+        };
+
+        let post = note_to_post(note, opts.verbose)
+            .with_context(|| format!("invalid note {}", item_id(note)))?;
+
+        posts.push(post);
+    }
+
+    eprintln!("{} notes read from {} outbox items", posts.len(), items.len());
+
+    let scribe_opts = ScribeOptions {
+        verbose: opts.verbose,
+        strict: opts.strict,
+        update: opts.update,
+    };
+
+    // Parse overrides are keyed by tweet id, so none can apply to ActivityPub posts
+    store_posts(db_pool, Platform::ActivityPub, posts, &ParseOverrides::default(), scribe_opts).await
+}
+
+/// Scribes the imported posts of the given platform in the order they were published, then prints
+/// the ids of the stored robots. As with fetch, posts which are already stored are left out unless
+/// they are being updated.
+async fn store_posts(
+    db_pool: &PgPool,
+    platform: Platform,
+    mut posts: Vec<RobotPost>,
+    overrides: &ParseOverrides,
+    scribe_opts: ScribeOptions
+) -> anyhow::Result<()>
+{
+    posts.sort_by_key(|post| post.time);
+
+    let mut seen = HashSet::new();
+    posts.retain(|post| seen.insert(post.source.clone()));
+
+    if !scribe_opts.update {
+        let existing_refs = sqlx::query_scalar::<_, String>(
+            "SELECT source_ref FROM robot_groups WHERE platform = $1 AND source_ref = ANY($2)"
+        )
+        .bind(platform)
+        .bind(posts.iter().map(|post| post.source.source_ref()).collect::<Vec<_>>())
+        .fetch_all(db_pool)
+        .await
+        .context("failed to check for existing posts")?
+        .into_iter()
+        .collect::<HashSet<_>>();
+
+        let n_posts = posts.len();
+        posts.retain(|post| !existing_refs.contains(&post.source.source_ref()));
+
+        eprintln!("{} already stored", n_posts - posts.len());
+    }
+
+    let mut db_conn = db_pool.acquire()
+        .await
+        .context("failed to connect to database")?;
+
+    let scribed = scribe::scribe_posts(&mut db_conn, &posts, overrides, scribe_opts)
+        .await
+        .context("failed to store posts")?;
+
+    scribe::report_stored(&scribe::stored_robots(&scribed), scribe_opts.update);
+
+    Ok(())
+}
+
+/// Finds the files containing Tweets in a Twitter archive. The path may be a single file, the
+/// archive's data directory or the extracted archive itself. Older archives use `tweet.js` rather
+/// than `tweets.js`, and large archives split the Tweets across `tweets-part1.js` and so on.
+async fn archive_tweet_files(path: &Path) -> anyhow::Result<Vec<PathBuf>> {
+    let metadata = tokio::fs::metadata(path)
+        .await
+        .with_context(|| format!("failed to read {}", path.to_string_lossy()))?;
+
+    if metadata.is_file() {
+        return Ok(vec![path.to_owned()]);
+    }
+
+    for dir in [path.join("data"), path.to_owned()] {
+        let mut read_dir = match tokio::fs::read_dir(&dir).await {
+            Ok(read_dir) => read_dir,
+            Err(_) => continue,
+        };
+
+        let mut files = Vec::new();
+
+        while let Some(dir_entry) = read_dir.next_entry().await? {
+            let file_name = dir_entry.file_name();
+            let file_name = file_name.to_string_lossy();
+
+            if is_tweet_file_name(&file_name) {
+                files.push(dir_entry.path());
+            }
+        }
+
+        if !files.is_empty() {
+            files.sort_unstable();
+            return Ok(files);
+        }
+    }
+
+    Err(anyhow!("no tweets.js files found in {}", path.to_string_lossy()))
+}
+
+fn is_tweet_file_name(file_name: &str) -> bool {
+    let stem = match file_name.strip_suffix(".js") {
+        Some(stem) => stem,
+        None => return false,
+    };
+
+    let base = match stem.split_once("-part") {
+        Some((base, part)) if !part.is_empty() && part.bytes().all(|b| b.is_ascii_digit()) => base,
+        Some(_) => return false,
+        None => stem,
+    };
+
+    base == "tweets" || base == "tweet"
+}
+
+/// Parses the contents of an archive file, which is a JavaScript assignment of the form
+/// `window.YTD.tweets.part0 = [...]`.
+fn parse_archive_file(contents: &str) -> anyhow::Result<Vec<ArchiveEntry>> {
+    let (_, json) = contents
+        .split_once('=')
+        .ok_or_else(|| anyhow!("missing assignment"))?;
+
+    serde_json::from_str(json.trim().trim_end_matches(';'))
+        .context("invalid tweets json")
+}
+
+#[derive(Deserialize, Debug)]
+struct ArchiveEntry {
+    tweet: ArchiveTweet,
+}
+
+/// A Tweet as it appears in a Twitter archive. Numbers are generally given as strings.
+#[derive(Deserialize, Debug)]
+struct ArchiveTweet {
+    id_str: String,
+    created_at: String,
+    full_text: String,
+    #[serde(default)]
+    entities: ArchiveEntities,
+    #[serde(default)]
+    extended_entities: Option<ArchiveExtendedEntities>,
+}
+
+#[derive(Deserialize, Default, Debug)]
+struct ArchiveEntities {
+    #[serde(default)]
+    hashtags: Vec<ArchiveHashtag>,
+    #[serde(default)]
+    user_mentions: Vec<ArchiveMention>,
+    #[serde(default)]
+    urls: Vec<ArchiveUrl>,
+    #[serde(default)]
+    media: Vec<ArchiveMedia>,
+}
+
+#[derive(Deserialize, Debug)]
+struct ArchiveExtendedEntities {
+    #[serde(default)]
+    media: Vec<ArchiveMedia>,
+}
+
+#[derive(Deserialize, Debug)]
+struct ArchiveHashtag {
+    text: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct ArchiveMention {
+    id_str: String,
+    screen_name: String,
+    name: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct ArchiveUrl {
+    url: String,
+    expanded_url: String,
+    display_url: String,
+    indices: [NumberString; 2],
+}
+
+#[derive(Deserialize, Debug)]
+struct ArchiveMedia {
+    id_str: String,
+    #[serde(rename = "type")]
+    media_type: String,
+    url: String,
+    display_url: String,
+    expanded_url: String,
+    media_url_https: String,
+    indices: [NumberString; 2],
+    #[serde(default)]
+    ext_alt_text: Option<String>,
+    #[serde(default)]
+    video_info: Option<ArchiveVideoInfo>,
+}
+
+#[derive(Deserialize, Debug)]
+struct ArchiveVideoInfo {
+    #[serde(default)]
+    duration_millis: Option<NumberString>,
+    #[serde(default)]
+    variants: Vec<ArchiveVideoVariant>,
+}
+
+#[derive(Deserialize, Debug)]
+struct ArchiveVideoVariant {
+    #[serde(default)]
+    bitrate: Option<NumberString>,
+    content_type: String,
+    url: String,
+}
+
+/// A number which may be given as either a json string or a json number.
+#[derive(Deserialize, Debug)]
+#[serde(untagged)]
+enum NumberString {
+    String(String),
+    Number(u64),
+}
+
+impl NumberString {
+    fn parse(&self) -> anyhow::Result<u64> {
+        match self {
+            Self::String(s) => s.parse().with_context(|| format!(r#"invalid number "{}""#, s)),
+            Self::Number(n) => Ok(*n),
+        }
+    }
+}
+
+impl ArchiveTweet {
+    /// Converts the archived tweet to a post in the same form as tweets returned by the Twitter
+    /// API. Returns `None` for retweets, which archives only mark by their text.
+    fn into_post(self) -> anyhow::Result<Option<RobotPost>> {
+        if self.full_text.starts_with("RT @") {
+            return Ok(None);
+        }
+
+        let tweet_id = self.id_str.parse::<u64>()
+            .with_context(|| format!(r#"invalid tweet id "{}""#, self.id_str))?;
+
+        let time = DateTime::parse_from_str(&self.created_at, "%a %b %d %H:%M:%S %z %Y")
+            .with_context(|| format!(r#"invalid tweet time "{}""#, self.created_at))?
+            .with_timezone(&Utc);
+
+        // The extended entities contain every media item, whereas the plain entities only contain
+        // the first
+        let media = match self.extended_entities {
+            Some(extended_entities) if !extended_entities.media.is_empty() => extended_entities.media,
+            _ => self.entities.media,
+        };
+
+        let media = media
+            .into_iter()
+            .map(ArchiveMedia::into_raw_media)
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        let urls = self.entities.urls
+            .into_iter()
+            .map(|url| Ok(PostUrl {
+                indices: Some(parse_indices(&url.indices)?),
+                url: url.url,
+                expanded_url: url.expanded_url,
+                display_url: url.display_url,
+            }))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        // Leave the media links and urls out of the text, matching the text options used for
+        // tweets from the API. Every media item of a tweet shares the same link and indices.
+        let removed_ranges = media
+            .iter()
+            .filter_map(|media| media.indices)
+            .chain(urls.iter().filter_map(|url| url.indices))
+            .collect::<Vec<_>>();
+
+        // The entity indices count the characters of the unescaped text
+        let full_text = unescape_html(&self.full_text);
+
+        let text = scribe::remove_char_ranges(&full_text, &removed_ranges)
+            .trim()
+            .to_owned();
+
+        let mentions = self.entities.user_mentions
+            .into_iter()
+            .map(|mention| Ok(PostMention {
+                user_id: mention.id_str.parse()
+                    .with_context(|| format!(r#"invalid user id "{}""#, mention.id_str))?,
+                handle: mention.screen_name,
+                display_name: mention.name,
+            }))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        Ok(Some(RobotPost {
+            source: PostSource::Tweet(tweet_id),
+            time,
+            full_text,
+            text,
+            content_warnings: Vec::new(),
+            media,
+            hashtags: self.entities.hashtags
+                .into_iter()
+                .map(|hashtag| hashtag.text)
+                .collect(),
+            mentions,
+            urls,
+        }))
+    }
+}
+
+fn parse_indices([start, end]: &[NumberString; 2]) -> anyhow::Result<[usize; 2]> {
+    Ok([start.parse()? as usize, end.parse()? as usize])
+}
+
+impl ArchiveMedia {
+    fn into_raw_media(self) -> anyhow::Result<RawMedia> {
+        let video_info = match self.video_info {
+            Some(video_info) => Some(RawVideoInfo {
+                duration_millis: video_info.duration_millis
+                    .as_ref()
+                    .map(NumberString::parse)
+                    .transpose()?,
+                variants: video_info.variants
+                    .into_iter()
+                    .map(|variant| Ok(RawVideoVariant {
+                        bitrate: variant.bitrate
+                            .as_ref()
+                            .map(NumberString::parse)
+                            .transpose()?,
+                        content_type: variant.content_type,
+                        url: variant.url,
+                    }))
+                    .collect::<anyhow::Result<Vec<_>>>()?,
+            }),
+            None => None,
+        };
+
+        Ok(RawMedia {
+            id: self.id_str.parse()
+                .with_context(|| format!(r#"invalid media id "{}""#, self.id_str))?,
+            media_type: self.media_type,
+            url: self.url,
+            display_url: self.display_url,
+            expanded_url: self.expanded_url,
+            media_url: self.media_url_https,
+            alt: self.ext_alt_text.unwrap_or_default(),
+            video_info,
+            indices: Some(parse_indices(&self.indices)?),
+        })
+    }
+}
+
+/// Reads every item of an ActivityPub outbox, following its pages if it is read from a url.
+async fn outbox_items(outbox: &str) -> anyhow::Result<Vec<Value>> {
+    let http_client = match outbox.starts_with("http://") || outbox.starts_with("https://") {
+        true => Some(reqwest::Client::new()),
+        false => None,
+    };
+
+    let collection = match &http_client {
+        Some(http_client) => get_activitypub_json(http_client, outbox).await?,
+
+        None => {
+            let contents = tokio::fs::read_to_string(outbox)
+                .await
+                .with_context(|| format!("failed to read outbox file {}", outbox))?;
+
+            serde_json::from_str(&contents)
+                .with_context(|| format!("invalid outbox json in {}", outbox))?
+        },
+    };
+
+    let mut items = collection_items(&collection);
+
+    // The items of a large outbox are split across pages, which may be embedded in the outbox or
+    // referred to by url
+    let mut visited_pages = HashSet::new();
+    let mut next_page = collection.get("first").cloned();
+
+    while let Some(page_ref) = next_page.take() {
+        let page = match page_ref {
+            Value::String(page_url) => {
+                // Rather than importing only part of the outbox
+                let http_client = http_client.as_ref().ok_or_else(|| anyhow!(
+                    "outbox file {} links to the page {} rather than containing its posts; read the \
+                    outbox from its url instead",
+                    outbox, page_url
+                ))?;
+
+                // Guard against servers which link pages in a cycle
+                if !visited_pages.insert(page_url.clone()) {
+                    break;
+                }
+
+                get_activitypub_json(http_client, &page_url).await?
+            },
+
+            page => page,
+        };
+
+        items.extend(collection_items(&page));
+        next_page = page.get("next").cloned();
+    }
+
+    Ok(items)
+}
+
+async fn get_activitypub_json(http_client: &reqwest::Client, url: &str) -> anyhow::Result<Value> {
+    let response = http_client
+        .get(url)
+        .header(reqwest::header::ACCEPT, "application/activity+json, application/ld+json")
+        .send()
+        .await
+        .and_then(reqwest::Response::error_for_status)
+        .with_context(|| format!("failed to request {}", url))?
+        .text()
+        .await
+        .with_context(|| format!("failed to read response from {}", url))?;
+
+    serde_json::from_str(&response)
+        .with_context(|| format!("invalid activitypub json from {}", url))
+}
+
+fn collection_items(collection: &Value) -> Vec<Value> {
+    collection.get("orderedItems")
+        .or_else(|| collection.get("items"))
+        .and_then(Value::as_array)
+        .cloned()
+        .unwrap_or_default()
+}
+
+/// Returns the note created by an outbox item, or the item itself if it is a note. Other items,
+/// such as boosts, are not robot posts.
+fn outbox_note(item: &Value) -> Option<&Value> {
+    match item.get("type")?.as_str()? {
+        "Create" => item.get("object").filter(|object| object_type(object) == Some("Note")),
+        "Note" => Some(item),
+        _ => None,
+    }
+}
+
+fn object_type(object: &Value) -> Option<&str> {
+    object.get("type")?.as_str()
+}
+
+fn item_id(item: &Value) -> &str {
+    item.get("id")
+        .and_then(Value::as_str)
+        .unwrap_or("without id")
+}
+
+/// Converts an ActivityPub note to a post. Image attachments become the post's media, with their
+/// names as alt text. The note's summary is used as a content warning; if it has none but is marked
+/// sensitive, the content warning "sensitive" is used instead.
+fn note_to_post(note: &Value, verbose: bool) -> anyhow::Result<RobotPost> {
+    let object_id = note.get("id")
+        .and_then(Value::as_str)
+        .ok_or_else(|| anyhow!("missing id"))?;
+
+    let published = note.get("published")
+        .and_then(Value::as_str)
+        .ok_or_else(|| anyhow!("missing published time"))?;
+
+    let time = DateTime::parse_from_rfc3339(published)
+        .with_context(|| format!(r#"invalid published time "{}""#, published))?
+        .with_timezone(&Utc);
+
+    let text = note.get("content")
+        .and_then(Value::as_str)
+        .map(html_to_text)
+        .unwrap_or_default();
+
+    let summary = note.get("summary")
+        .and_then(Value::as_str)
+        .map(str::trim)
+        .filter(|summary| !summary.is_empty());
+
+    let sensitive = note.get("sensitive")
+        .and_then(Value::as_bool)
+        .unwrap_or(false);
+
+    let content_warnings = match (summary, sensitive) {
+        (Some(summary), _) => vec![unescape_html(summary)],
+        (None, true) => vec!["sensitive".to_owned()],
+        (None, false) => Vec::new(),
+    };
+
+    let mut media = Vec::new();
+
+    for attachment in one_or_many(note.get("attachment")) {
+        let media_type = attachment.get("mediaType")
+            .and_then(Value::as_str)
+            .unwrap_or_default();
+
+        let url = attachment.get("url").and_then(link_href);
+
+        match url {
+            Some(url) if media_type.starts_with("image/") => media.push(RawMedia {
+                // Attachments have no numeric id, unlike Twitter media
+                id: 0,
+                media_type: "photo".to_owned(),
+                url: url.to_owned(),
+                display_url: url.to_owned(),
+                expanded_url: url.to_owned(),
+                media_url: url.to_owned(),
+                alt: attachment.get("name")
+                    .and_then(Value::as_str)
+                    .unwrap_or_default()
+                    .to_owned(),
+                video_info: None,
+                indices: None,
+            }),
+
+            _ => if verbose {
+                eprintln!("post {}: skipping unsupported attachment of type {:?}", object_id, media_type);
+            },
+        }
+    }
+
+    let hashtags = one_or_many(note.get("tag"))
+        .filter(|tag| object_type(tag) == Some("Hashtag"))
+        .filter_map(|tag| tag.get("name")?.as_str())
+        .map(|name| name.trim_start_matches('#').to_owned())
+        .collect();
+
+    Ok(RobotPost {
+        source: PostSource::ActivityPub(object_id.to_owned()),
+        time,
+        full_text: text.clone(),
+        text,
+        content_warnings,
+        media,
+        hashtags,
+        // Mentions and links are only stored for tweets
+        mentions: Vec::new(),
+        urls: Vec::new(),
+    })
+}
+
+/// Iterates over an ActivityPub property which may be either a single value or an array of them.
+fn one_or_many(value: Option<&Value>) -> impl Iterator<Item = &Value> {
+    let values = match value {
+        Some(Value::Array(values)) => values.iter().collect::<Vec<_>>(),
+        Some(Value::Null) | None => Vec::new(),
+        Some(value) => vec![value],
+    };
+
+    values.into_iter()
+}
+
+/// Returns the url of an ActivityPub link, which may be given as a plain url, a Link object or an
+/// array of either.
+fn link_href(link: &Value) -> Option<&str> {
+    match link {
+        Value::String(url) => Some(url),
+        Value::Array(links) => links.iter().find_map(link_href),
+        Value::Object(_) => link.get("href")?.as_str(),
+        _ => None,
+    }
+}
+
+/// Converts the html content of a note to plain text, with line breaks between paragraphs.
+fn html_to_text(html: &str) -> String {
+    let mut text = String::with_capacity(html.len());
+    let mut rest = html;
+
+    while let Some(tag_start) = rest.find('<') {
+        text.push_str(&rest[..tag_start]);
+        rest = &rest[tag_start + 1..];
+
+        let tag_len = rest.find('>').unwrap_or(rest.len());
+        let tag = &rest[..tag_len];
+        rest = rest.get(tag_len + 1..).unwrap_or_default();
+
+        let (closing, tag) = match tag.strip_prefix('/') {
+            Some(tag) => (true, tag),
+            None => (false, tag),
+        };
+
+        let tag_name = tag
+            .split(|c: char| c.is_ascii_whitespace() || c == '/')
+            .next()
+            .unwrap_or_default();
+
+        if tag_name.eq_ignore_ascii_case("br") {
+            text.push('\n');
+        } else if closing && tag_name.eq_ignore_ascii_case("p") {
+            text.push_str("\n\n");
+        }
+    }
+
+    text.push_str(rest);
+
+    unescape_html(&text).trim().to_owned()
+}
+
+/// Decodes the html character references in the text in a single pass, so that e.g. "&amp;lt;"
+/// becomes "&lt;" rather than "<". Numeric references are decoded, along with the named references
+/// which Twitter and Mastodon produce; any others are left as they are.
+fn unescape_html(text: &str) -> String {
+    let mut unescaped = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(amp) = rest.find('&') {
+        unescaped.push_str(&rest[..amp]);
+        rest = &rest[amp + 1..];
+
+        let decoded = rest
+            .split_once(';')
+            .and_then(|(reference, _)| Some((reference.len(), decode_char_ref(reference)?)));
+
+        match decoded {
+            Some((reference_len, c)) => {
+                unescaped.push(c);
+                rest = &rest[reference_len + 1..];
+            },
+            None => unescaped.push('&'),
+        }
+    }
+
+    unescaped.push_str(rest);
+    unescaped
+}
+
+/// Decodes the part of a character reference between the `&` and the `;`, such as `amp` or
+/// `#8217`.
+fn decode_char_ref(reference: &str) -> Option<char> {
+    match reference {
+        "amp" => Some('&'),
+        "lt" => Some('<'),
+        "gt" => Some('>'),
+        "quot" => Some('"'),
+        "apos" => Some('\''),
+        "nbsp" => Some('\u{a0}'),
+        _ => {
+            let number = reference.strip_prefix('#')?;
+
+            let code_point = match number.strip_prefix(|c| c == 'x' || c == 'X') {
+                Some(hex) if !hex.is_empty() && hex.bytes().all(|b| b.is_ascii_hexdigit()) =>
+                    u32::from_str_radix(hex, 16).ok()?,
+                None if !number.is_empty() && number.bytes().all(|b| b.is_ascii_digit()) =>
+                    number.parse().ok()?,
+                _ => return None,
+            };
+
+            char::from_u32(code_point)
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::{ArchiveTweet, PostSource};
+
+    fn note(extra: serde_json::Value) -> serde_json::Value {
+        let mut note = json!({
+            "id": "https://botsin.space/users/smolrobots/statuses/1",
+            "type": "Note",
+            "published": "2022-01-01T12:00:00Z",
+            "content": "<p>1) Teabot</p><p>They make tea.</p>",
+        });
+
+        note.as_object_mut()
+            .unwrap()
+            .extend(extra.as_object().unwrap().clone());
+
+        note
+    }
+
+    fn archive_tweet(full_text: &str, urls: serde_json::Value) -> ArchiveTweet {
+        serde_json::from_value(json!({
+            "id_str": "1234",
+            "created_at": "Sat Jan 01 12:00:00 +0000 2022",
+            "full_text": full_text,
+            "entities": {
+                "hashtags": [],
+                "user_mentions": [],
+                "urls": urls,
+            },
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_is_tweet_file_name() {
+        use super::is_tweet_file_name;
+
+        assert!(is_tweet_file_name("tweets.js"));
+        assert!(is_tweet_file_name("tweet.js"));
+        assert!(is_tweet_file_name("tweets-part1.js"));
+        assert!(is_tweet_file_name("tweet-part12.js"));
+        assert!(!is_tweet_file_name("tweets.json"));
+        assert!(!is_tweet_file_name("tweets-part.js"));
+        assert!(!is_tweet_file_name("tweets-partone.js"));
+        assert!(!is_tweet_file_name("tweetdeck.js"));
+        assert!(!is_tweet_file_name("like.js"));
+        assert!(!is_tweet_file_name("deleted-tweets.js"));
+    }
+
+    #[test]
+    fn test_parse_archive_file() {
+        use super::parse_archive_file;
+
+        let contents = r#"window.YTD.tweets.part0 = [
+            {
+                "tweet": {
+                    "id_str": "1234",
+                    "created_at": "Sat Jan 01 12:00:00 +0000 2022",
+                    "full_text": "1) Equalsbot\n\nThey check that a = b."
+                }
+            }
+        ]"#;
+
+        let entries = parse_archive_file(contents).unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].tweet.id_str, "1234");
+        assert_eq!(entries[0].tweet.full_text, "1) Equalsbot\n\nThey check that a = b.");
+
+        let entries = parse_archive_file("window.YTD.tweet.part1 = [];").unwrap();
+        assert!(entries.is_empty());
+
+        assert!(parse_archive_file(r#"[{"tweet": {}}]"#).is_err());
+    }
+
+    #[test]
+    fn test_archive_tweet_into_post() {
+        let post = archive_tweet("1) Teabot\n\nThey make tea.", json!([]))
+            .into_post()
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(post.source, PostSource::Tweet(1234));
+        assert_eq!(post.text, "1) Teabot\n\nThey make tea.");
+        assert_eq!(post.time.to_rfc3339(), "2022-01-01T12:00:00+00:00");
+    }
+
+    #[test]
+    fn test_archive_retweets_skipped() {
+        let post = archive_tweet("RT @smolrobots: 1) Teabot\n\nThey make tea.", json!([]))
+            .into_post()
+            .unwrap();
+
+        assert!(post.is_none());
+    }
+
+    #[test]
+    fn test_archive_text_unescaped() {
+        let post = archive_tweet("1) Heartbot\n\nThey &lt;3 tea &amp; cake &gt;:)", json!([]))
+            .into_post()
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(post.text, "1) Heartbot\n\nThey <3 tea & cake >:)");
+    }
+
+    #[test]
+    fn test_archive_links_removed_by_index() {
+        // The second link begins with the first, so removing the first by its text would leave
+        // part of the second behind
+        let post = archive_tweet(
+            "1) Linkbot &amp; co https://t.co/ab\n\nSee https://t.co/abc",
+            json!([
+                {
+                    "url": "https://t.co/ab",
+                    "expanded_url": "https://example.com/a",
+                    "display_url": "example.com/a",
+                    "indices": ["16", "31"],
+                },
+                {
+                    "url": "https://t.co/abc",
+                    "expanded_url": "https://example.com/b",
+                    "display_url": "example.com/b",
+                    "indices": ["37", "53"],
+                },
+            ]),
+        )
+        .into_post()
+        .unwrap()
+        .unwrap();
+
+        assert_eq!(post.text, "1) Linkbot & co \n\nSee");
+        assert_eq!(post.full_text, "1) Linkbot & co https://t.co/ab\n\nSee https://t.co/abc");
+        assert_eq!(post.urls.len(), 2);
+        assert_eq!(post.urls[1].indices, Some([37, 53]));
+        assert_eq!(post.urls[1].expanded_url, "https://example.com/b");
+    }
+
+    #[test]
+    fn test_unescape_html() {
+        use super::unescape_html;
+
+        assert_eq!(unescape_html("&lt;3 &amp; &gt;:)"), "<3 & >:)");
+        assert_eq!(unescape_html("&quot;hi&quot; &#39;there&apos;"), "\"hi\" 'there'");
+        assert_eq!(unescape_html("It&#8217;s &#x1F916; time"), "It\u{2019}s \u{1F916} time");
+        assert_eq!(unescape_html("&amp;lt; &amp;#39;"), "&lt; &#39;");
+        assert_eq!(unescape_html("Fish & chips; &unknown; &#xZZ; &#; &"), "Fish & chips; &unknown; &#xZZ; &#; &");
+        assert_eq!(unescape_html("&#1114112;"), "&#1114112;");
+    }
+
+    #[test]
+    fn test_html_to_text() {
+        use super::html_to_text;
+
+        assert_eq!(
+            html_to_text(
+                "<p>1) Teabot</p><p>They make tea for everyone&#39;s friends &amp; family. It&#8217;s \
+                great!<br />Not too hot <a href=\"https://botsin.space/tags/smolrobots\" class=\"mention \
+                hashtag\" rel=\"tag\">#<span>smolrobots</span></a></p>"
+            ),
+            "1) Teabot\n\nThey make tea for everyone's friends & family. It\u{2019}s great!\nNot too hot \
+            #smolrobots"
+        );
+
+        assert_eq!(
+            html_to_text(
+                "<p>2) Linkbot<br>See <a href=\"https://example.com/linkbot\" target=\"_blank\" \
+                rel=\"nofollow noopener noreferrer\"><span class=\"invisible\">https://</span><span \
+                class=\"\">example.com/linkbot</span><span class=\"invisible\"></span></a></p>"
+            ),
+            "2) Linkbot\nSee https://example.com/linkbot"
+        );
+
+        assert_eq!(html_to_text("<P>a &lt;b&gt;</P><p>c<BR/>d</p>"), "a <b>\n\nc\nd");
+        assert_eq!(html_to_text("no tags"), "no tags");
+    }
+
+    #[test]
+    fn test_note_content_warnings() {
+        use super::note_to_post;
+
+        let post = note_to_post(&note(json!({})), false).unwrap();
+        assert_eq!(post.source, PostSource::ActivityPub("https://botsin.space/users/smolrobots/statuses/1".to_owned()));
+        assert_eq!(post.text, "1) Teabot\n\nThey make tea.");
+        assert!(post.content_warnings.is_empty());
+
+        let post = note_to_post(&note(json!({ "summary": "spiders &amp; bugs", "sensitive": true })), false).unwrap();
+        assert_eq!(post.content_warnings, vec!["spiders & bugs".to_owned()]);
+
+        let post = note_to_post(&note(json!({ "summary": "", "sensitive": true })), false).unwrap();
+        assert_eq!(post.content_warnings, vec!["sensitive".to_owned()]);
+
+        let post = note_to_post(&note(json!({ "summary": null, "sensitive": false })), false).unwrap();
+        assert!(post.content_warnings.is_empty());
+    }
+
+    #[test]
+    fn test_note_attachments() {
+        use super::note_to_post;
+
+        let post = note_to_post(&note(json!({
+            "attachment": [
+                {
+                    "type": "Document",
+                    "mediaType": "image/png",
+                    "url": "https://files.botsin.space/teabot.png",
+                    "name": "A small robot holding a teapot",
+                },
+                {
+                    "type": "Document",
+                    "mediaType": "video/mp4",
+                    "url": "https://files.botsin.space/teabot.mp4",
+                },
+                {
+                    "type": "Image",
+                    "mediaType": "image/jpeg",
+                    "url": [{ "type": "Link", "href": "https://files.botsin.space/teabot.jpg" }],
+                },
+            ],
+            "tag": [
+                { "type": "Hashtag", "name": "#smolrobots" },
+                { "type": "Mention", "name": "@someone" },
+            ],
+        })), false).unwrap();
+
+        assert_eq!(post.media.len(), 2);
+
+        assert_eq!(post.media[0].media_type, "photo");
+        assert_eq!(post.media[0].url, "https://files.botsin.space/teabot.png");
+        assert_eq!(post.media[0].media_url, "https://files.botsin.space/teabot.png");
+        assert_eq!(post.media[0].alt, "A small robot holding a teapot");
+        assert!(post.media[0].video_info.is_none());
+
+        assert_eq!(post.media[1].media_url, "https://files.botsin.space/teabot.jpg");
+        assert_eq!(post.media[1].alt, "");
+
+        assert_eq!(post.hashtags, vec!["smolrobots".to_owned()]);
+
+        let post = note_to_post(&note(json!({
+            "attachment": {
+                "type": "Document",
+                "mediaType": "image/gif",
+                "url": "https://files.botsin.space/teabot.gif",
+            },
+        })), false).unwrap();
+
+        assert_eq!(post.media.len(), 1);
+    }
+
+    #[test]
+    fn test_outbox_note() {
+        use super::outbox_note;
+
+        let create = json!({ "type": "Create", "object": note(json!({})) });
+        assert_eq!(outbox_note(&create), Some(&create["object"]));
+
+        let announce = json!({ "type": "Announce", "object": "https://example.com/notes/1" });
+        assert_eq!(outbox_note(&announce), None);
+
+        let create_question = json!({ "type": "Create", "object": { "type": "Question" } });
+        assert_eq!(outbox_note(&create_question), None);
+    }
+
+    #[tokio::test]
+    async fn test_outbox_file_linking_pages() {
+        use super::outbox_items;
+
+        let path = std::env::temp_dir().join(format!("sbb-test-outbox-{}.json", std::process::id()));
+
+        let outbox = json!({
+            "type": "OrderedCollection",
+            "totalItems": 1,
+            "first": "https://botsin.space/users/smolrobots/outbox?page=true",
+        });
+
+        tokio::fs::write(&path, outbox.to_string()).await.unwrap();
+        let res = outbox_items(path.to_str().unwrap()).await;
+        tokio::fs::remove_file(&path).await.unwrap();
+
+        let err = res.unwrap_err().to_string();
+        assert!(err.contains("read the outbox from its url"), "{}", err);
+
+        let outbox = json!({
+            "type": "OrderedCollection",
+            "orderedItems": [{ "type": "Create", "object": note(json!({})) }],
+        });
+
+        tokio::fs::write(&path, outbox.to_string()).await.unwrap();
+        let res = outbox_items(path.to_str().unwrap()).await;
+        tokio::fs::remove_file(&path).await.unwrap();
+
+        assert_eq!(res.unwrap().len(), 1);
+    }
+}
@@ -1,4 +1,5 @@
 mod parse;
+mod parse_cmd;
 mod fetch;
 mod export;
 mod timeline;
@@ -10,6 +11,14 @@ mod model;
 mod error;
 mod plural;
 mod ident;
+mod fold;
+mod reparse;
+mod overrides;
+mod quarantine;
+mod robot;
+mod verify;
+mod report;
+mod import;
 
 use std::default::Default;
 use std::env;
@@ -48,7 +57,7 @@ enum MainCommand {
     /// Read a user's timeline, searching for new robot Tweets.
     Timeline(timeline::Opts),
 
-    /// Download robot images and/or generate thumbnails.
+    /// Download robot images and videos and/or generate thumbnails.
     Image(images::Opts),
 
     /// Post a new Tweet.
@@ -56,6 +65,24 @@ enum MainCommand {
 
     /// Import or export custom alt text.
     Alt(alt::Opts),
+
+    /// Parse robot Tweet text and print the result as JSON, without storing anything.
+    Parse(parse_cmd::Opts),
+
+    /// Reparse stored robot Tweets, showing and optionally applying any changes.
+    Reparse(reparse::Opts),
+
+    /// Review Tweets which could not be stored as robots.
+    Quarantine(quarantine::Opts),
+
+    /// Manually add, edit or remove robots.
+    Robot(robot::Opts),
+
+    /// Check that the Tweets of stored robots still exist, marking any which have been deleted.
+    VerifyTweets(verify::Opts),
+
+    /// Store robots from an offline export, without using the Twitter API.
+    Import(import::Opts),
 }
 
 #[derive(Deserialize, Default)]
@@ -187,6 +214,44 @@ async fn run(opts: Opts, config: Config) -> anyhow::Result<()> {
             db_pool.close().await;
             res
         },
+
+        MainCommand::Parse(opts) => parse_cmd::run(opts).await,
+
+        MainCommand::Reparse(opts) => {
+            let db_pool = connect_db(config.database.unwrap_or_default()).await?;
+            let res = reparse::run(&db_pool, opts).await;
+            db_pool.close().await;
+            res
+        },
+
+        MainCommand::Quarantine(opts) => {
+            let db_pool = connect_db(config.database.unwrap_or_default()).await?;
+            let res = quarantine::run(&db_pool, config.goldcrest.unwrap_or_default(), opts).await;
+            db_pool.close().await;
+            res
+        },
+
+        MainCommand::Robot(opts) => {
+            let db_pool = connect_db(config.database.unwrap_or_default()).await?;
+            let res = robot::run(&db_pool, opts).await;
+            db_pool.close().await;
+            res
+        },
+
+        MainCommand::VerifyTweets(opts) => {
+            let db_pool = connect_db(config.database.unwrap_or_default()).await?;
+            let au_client = connect_goldcrest(config.goldcrest.unwrap_or_default()).await?;
+            let res = verify::run(&db_pool, &au_client, opts).await;
+            db_pool.close().await;
+            res
+        },
+
+        MainCommand::Import(opts) => {
+            let db_pool = connect_db(config.database.unwrap_or_default()).await?;
+            let res = import::run(&db_pool, opts).await;
+            db_pool.close().await;
+            res
+        },
     }
 }
 
@@ -3,10 +3,14 @@ use std::fmt;
 use std::str::FromStr;
 use std::num::ParseIntError;
 
+use chrono::{DateTime, Utc};
+use goldcrest::data::Media;
+use serde::{Serialize, Deserialize};
 use sqlx::{FromRow, Type};
 use sqlx::postgres::{PgHasArrayType, PgTypeInfo};
+use sqlx::types::Json;
 
-#[derive(Type, Clone, Debug)]
+#[derive(Type, PartialEq, Eq, Hash, Clone, Debug)]
 #[sqlx(type_name = "robot_ident")]
 pub struct IdentBuf {
     pub number: i32,
@@ -72,19 +76,58 @@ impl fmt::Display for ParseIdentError {
 
 impl error::Error for ParseIdentError {}
 
+/// The platform that a robot group was published on.
+#[derive(Type, PartialEq, Eq, Clone, Copy, Debug)]
+#[sqlx(type_name = "robot_platform", rename_all = "lowercase")]
+pub(crate) enum Platform {
+    Twitter,
+    ActivityPub,
+    /// Added with `sbb robot add` without being published anywhere.
+    Manual,
+}
+
 #[derive(FromRow)]
 pub(crate) struct TweetId {
     pub(crate) tweet_id: i64,
 }
 
+/// A group of robots published together, and the fields which they share.
+#[derive(FromRow, PartialEq, Clone, Debug)]
+pub(crate) struct RobotGroup {
+    pub(crate) id: i32,
+    /// `None` if the group was added manually rather than coming from a tweet.
+    pub(crate) tweet_id: Option<i64>,
+    pub(crate) tweet_time: DateTime<Utc>,
+    pub(crate) image_url: String,
+    pub(crate) body: String,
+    pub(crate) alt: Option<String>,
+    pub(crate) content_warnings: Vec<String>,
+}
+
+/// A robot as stored in the `robots` table. The fields it shares with the other robots published
+/// alongside it are stored in its `RobotGroup`.
+#[derive(FromRow, PartialEq, Clone, Debug)]
+pub(crate) struct RobotRecord {
+    pub(crate) id: IdentBuf,
+    pub(crate) group_id: i32,
+    pub(crate) prefix: String,
+    pub(crate) suffix: String,
+    pub(crate) plural: Option<String>,
+    pub(crate) needs_review: bool,
+}
+
 #[derive(FromRow)]
 pub(crate) struct DailyRobot {
     pub(crate) id: IdentBuf,
     pub(crate) prefix: String,
     pub(crate) suffix: String,
     pub(crate) plural: Option<String>,
-    pub(crate) tweet_id: i64,
-    pub(crate) content_warning: Option<String>,
+    pub(crate) platform: Platform,
+    /// The id of the robot's post on its platform, or `None` if it was added manually.
+    pub(crate) source_ref: Option<String>,
+    /// Whether the robot's post has been found to no longer exist.
+    pub(crate) tweet_deleted: bool,
+    pub(crate) content_warnings: Vec<String>,
 }
 
 impl DailyRobot {
@@ -105,27 +148,51 @@ impl DailyRobot {
         name_buf
     }
 
-    pub(crate) fn tweet_url(&self) -> String {
-        format!("https://twitter.com/smolrobots/status/{}", self.tweet_id)
+    /// Returns the url of the robot's post, or `None` if the robot was added manually without one
+    /// or its post has since been deleted.
+    pub(crate) fn post_url(&self) -> Option<String> {
+        if self.tweet_deleted {
+            return None;
+        }
+
+        let source_ref = self.source_ref.as_deref()?;
+
+        match self.platform {
+            Platform::Twitter => Some(format!("https://twitter.com/smolrobots/status/{}", source_ref)),
+            // ActivityPub object ids are the urls of the objects
+            Platform::ActivityPub => Some(source_ref.to_owned()),
+            Platform::Manual => None,
+        }
     }
 }
 
+/// The media of a robot group, identified in messages and file names by the group's first robot.
 #[derive(FromRow, Clone, Debug)]
 pub(crate) struct RobotImageUrl {
+    pub(crate) group_id: i32,
     pub(crate) id: IdentBuf,
+    pub(crate) position: i32,
     pub(crate) image_url: String,
+    /// The url of the best mp4 encoding of the media, if it is an animated gif or video.
+    pub(crate) video_url: Option<String>,
 }
 
 #[derive(FromRow, Clone, Debug)]
 pub(crate) struct RobotImagePath {
+    pub(crate) group_id: i32,
     pub(crate) id: IdentBuf,
+    pub(crate) position: i32,
     pub(crate) image_path: String,
+    pub(crate) video_path: Option<String>,
 }
 
 #[derive(FromRow, Clone, Debug)]
 pub(crate) struct RobotImagePathOpt {
+    pub(crate) group_id: i32,
     pub(crate) id: IdentBuf,
+    pub(crate) position: i32,
     pub(crate) image_path: Option<String>,
+    pub(crate) video_path: Option<String>,
 }
 
 #[derive(FromRow, Clone, Debug)]
@@ -133,3 +200,109 @@ pub(crate) struct RobotCustomAltExport {
     pub(crate) id: IdentBuf,
     pub(crate) custom_alt: String,
 }
+
+#[derive(FromRow, Clone, Debug)]
+pub(crate) struct RawTweet {
+    pub(crate) tweet_id: i64,
+    pub(crate) tweet_time: DateTime<Utc>,
+    pub(crate) full_text: String,
+    pub(crate) entities: Json<RawEntities>,
+    pub(crate) media: Json<Vec<RawMedia>>,
+}
+
+/// The entities of a tweet, as stored in the `entities` column of `raw_tweets`. Only the urls are
+/// needed to reparse the tweet.
+#[derive(Deserialize, Clone, Debug)]
+pub(crate) struct RawEntities {
+    #[serde(default)]
+    pub(crate) urls: Vec<RawUrl>,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+pub(crate) struct RawUrl {
+    /// The range of character indices of the url in the full text of the tweet.
+    #[serde(default)]
+    pub(crate) indices: Option<[usize; 2]>,
+}
+
+/// A media item of a tweet, as stored in the `media` column of `raw_tweets`.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub(crate) struct RawMedia {
+    pub(crate) id: u64,
+    pub(crate) media_type: String,
+    pub(crate) url: String,
+    pub(crate) display_url: String,
+    pub(crate) expanded_url: String,
+    pub(crate) media_url: String,
+    pub(crate) alt: String,
+    #[serde(default)]
+    pub(crate) video_info: Option<RawVideoInfo>,
+    /// The range of character indices of the media link in the full text of the tweet. Every
+    /// media item of a tweet shares the same link.
+    #[serde(default)]
+    pub(crate) indices: Option<[usize; 2]>,
+}
+
+/// The playback information of an animated gif or video, as stored in `raw_tweets`.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub(crate) struct RawVideoInfo {
+    pub(crate) duration_millis: Option<u64>,
+    pub(crate) variants: Vec<RawVideoVariant>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub(crate) struct RawVideoVariant {
+    pub(crate) bitrate: Option<u64>,
+    pub(crate) content_type: String,
+    pub(crate) url: String,
+}
+
+impl From<&Media> for RawMedia {
+    fn from(media: &Media) -> Self {
+        Self {
+            id: media.id,
+            media_type: media.media_type.clone(),
+            url: media.url.clone(),
+            display_url: media.display_url.clone(),
+            expanded_url: media.expanded_url.clone(),
+            media_url: media.media_url.clone(),
+            alt: media.alt.clone(),
+            video_info: media.video_info.as_ref().map(|video_info| RawVideoInfo {
+                duration_millis: video_info.duration_millis,
+                variants: video_info.variants
+                    .iter()
+                    .map(|variant| RawVideoVariant {
+                        bitrate: variant.bitrate,
+                        content_type: variant.content_type.clone(),
+                        url: variant.url.clone(),
+                    })
+                    .collect(),
+            }),
+            indices: None,
+        }
+    }
+}
+
+#[derive(FromRow, Clone, Debug)]
+pub(crate) struct QuarantinedTweet {
+    pub(crate) tweet_id: i64,
+    pub(crate) tweet_time: DateTime<Utc>,
+    pub(crate) text: String,
+    pub(crate) reason: String,
+    pub(crate) first_seen_at: DateTime<Utc>,
+    pub(crate) last_seen_at: DateTime<Utc>,
+    pub(crate) dismissed: bool,
+}
+
+/// The fields of a robot which are derived from parsing its tweet.
+#[derive(FromRow, PartialEq, Clone, Debug)]
+pub(crate) struct RobotParsedFields {
+    pub(crate) id: IdentBuf,
+    pub(crate) tweet_id: i64,
+    pub(crate) prefix: String,
+    pub(crate) suffix: String,
+    pub(crate) plural: Option<String>,
+    pub(crate) body: String,
+    pub(crate) content_warnings: Vec<String>,
+    pub(crate) needs_review: bool,
+}
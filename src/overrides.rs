@@ -0,0 +1,138 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::path::Path;
+
+use anyhow::{anyhow, Context};
+use serde::Deserialize;
+
+use crate::parse::{self, ParsedGroup, Robot, RobotName};
+
+/// Manual corrections for robot tweets which the parser gets wrong, keyed by tweet id. When a
+/// tweet has an override, it is used in place of the output of `parse::parse_group`.
+///
+/// The overrides are read from a json file of the form:
+///
+/// ```json
+/// [
+///   {
+///     "tweet": "1234567890123456789",
+///     "robots": [{ "number": 558, "prefix": "Salt", "suffix": "bot", "plural": null }],
+///     "body": "Seasons your food.",
+///     "content_warnings": []
+///   }
+/// ]
+/// ```
+#[derive(Default, Debug)]
+pub(crate) struct ParseOverrides {
+    overrides: HashMap<u64, ParseOverride>,
+}
+
+#[derive(Debug)]
+pub(crate) struct ParseOverride {
+    robots: Vec<OverrideRobot>,
+    body: String,
+    content_warnings: Vec<String>,
+}
+
+type OverrideEntries = Vec<OverrideEntry>;
+
+#[derive(Deserialize, Debug)]
+struct OverrideEntry {
+    /// The tweet id is stored as a string, because tweet ids are too large to be represented
+    /// exactly by JavaScript numbers.
+    tweet: String,
+    robots: Vec<OverrideRobot>,
+    body: String,
+    #[serde(default)]
+    content_warnings: Vec<String>,
+}
+
+#[derive(Deserialize, Debug)]
+struct OverrideRobot {
+    number: i32,
+    prefix: String,
+    suffix: String,
+    #[serde(default)]
+    plural: Option<String>,
+}
+
+impl ParseOverrides {
+    /// Reads the overrides from the given json file, or returns an empty set of overrides if no
+    /// file is given.
+    pub(crate) async fn load(path: Option<&Path>) -> anyhow::Result<Self> {
+        let path = match path {
+            Some(path) => path,
+            None => return Ok(Self::default()),
+        };
+
+        let contents = tokio::fs::read_to_string(path)
+            .await
+            .with_context(|| format!("failed to read overrides file {}", path.to_string_lossy()))?;
+
+        Self::from_json(&contents)
+            .with_context(|| format!("invalid overrides file {}", path.to_string_lossy()))
+    }
+
+    fn from_json(json: &str) -> anyhow::Result<Self> {
+        let entries = serde_json::from_str::<OverrideEntries>(json)?;
+
+        let mut overrides = HashMap::with_capacity(entries.len());
+
+        for entry in entries {
+            let tweet_id = entry.tweet.parse::<u64>()
+                .with_context(|| format!(r#"invalid tweet id "{}""#, entry.tweet))?;
+
+            if entry.robots.is_empty() {
+                return Err(anyhow!("no robots given for tweet {}", tweet_id));
+            }
+
+            let mut content_warnings = Vec::<String>::new();
+            for cw in entry.content_warnings.iter().filter_map(|cw| parse::normalise_cw(cw)) {
+                if !content_warnings.contains(&cw) {
+                    content_warnings.push(cw);
+                }
+            }
+
+            let prev = overrides.insert(tweet_id, ParseOverride {
+                robots: entry.robots,
+                body: entry.body,
+                content_warnings,
+            });
+
+            if prev.is_some() {
+                return Err(anyhow!("tweet {} is overridden more than once", tweet_id));
+            }
+        }
+
+        Ok(Self { overrides })
+    }
+
+    pub(crate) fn get(&self, tweet_id: u64) -> Option<&ParseOverride> {
+        self.overrides.get(&tweet_id)
+    }
+}
+
+impl ParseOverride {
+    /// Returns the override in the same form as the output of `parse::parse_group`. Overrides
+    /// never have any parse warnings, since they have already been checked by hand.
+    pub(crate) fn group(&self) -> ParsedGroup<'_> {
+        let robots = self.robots
+            .iter()
+            .map(|robot| Robot {
+                number: robot.number,
+                name: RobotName {
+                    prefix: Cow::Borrowed(&robot.prefix),
+                    suffix: Cow::Borrowed(&robot.suffix),
+                    plural: robot.plural.as_deref().map(Cow::Borrowed),
+                },
+            })
+            .collect();
+
+        ParsedGroup {
+            robots,
+            body: &self.body,
+            content_warnings: self.content_warnings.clone(),
+            warnings: Vec::new(),
+        }
+    }
+}
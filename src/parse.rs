@@ -1,11 +1,16 @@
 use std::borrow::Cow;
 use std::convert::TryFrom;
+use std::error;
+use std::fmt;
 use std::ops::RangeInclusive;
 
 use lazy_static::lazy_static;
 use regex::Regex;
+use serde::Serialize;
+use serde::ser::{Serializer, SerializeStruct};
 use unidecode::unidecode;
 
+use crate::fold::Folded;
 use crate::model::IdentBuf;
 
 /// The name and number of a single robot.
@@ -24,6 +29,23 @@ impl<'a> Robot<'a> {
     }
 }
 
+/// Serializes the robot's name components alongside its number and the ident that it would be
+/// stored under.
+impl Serialize for Robot<'_> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut state = serializer.serialize_struct("Robot", 5)?;
+        state.serialize_field("number", &self.number)?;
+        state.serialize_field("prefix", &self.name.prefix)?;
+        state.serialize_field("suffix", &self.name.suffix)?;
+        state.serialize_field("plural", &self.name.plural)?;
+        state.serialize_field("ident", &self.ident().to_string())?;
+        state.end()
+    }
+}
+
 /// The components of the name of a robot.
 #[derive(Clone, Eq, PartialEq, Debug)]
 pub struct RobotName<'a> {
@@ -51,7 +73,7 @@ pub struct RobotName<'a> {
 }
 
 /// The result of parsing a robot tweet.
-#[derive(Clone, Eq, PartialEq, Debug)]
+#[derive(Serialize, Clone, Eq, PartialEq, Debug)]
 pub struct ParsedGroup<'a> {
     /// All of the names and numbers of the robots found in the robot tweet.
     pub robots: Vec<Robot<'a>>,
@@ -59,16 +81,164 @@ pub struct ParsedGroup<'a> {
     /// The body text of the robot tweet.
     pub body: &'a str,
     
-    /// The content warning, if one was found before the robot's name.
-    pub cw: Option<&'a str>,
+    /// The normalised content warnings found before or immediately after the robot numbers.
+    pub content_warnings: Vec<String>,
+
+    /// Problems found while parsing which mean that the robots may be incomplete or wrong.
+    pub warnings: Vec<ParseWarning>,
+}
+
+/// A problem with a robot tweet which did not prevent it from being parsed, but which means that
+/// the parsed group should be checked by a human.
+#[derive(Serialize, Clone, Eq, PartialEq, Debug)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum ParseWarning {
+    /// Fewer robot names were found than there were robot numbers, so some robots are missing.
+    CountMismatch { numbers: usize, names: usize },
+
+    /// The range of robot numbers was larger than the maximum group size, so only the first `max`
+    /// numbers were used.
+    GroupTooLarge { numbers: usize, max: usize },
+
+    /// A name was found again before every robot number had a name, which usually means that the
+    /// tweet named fewer robots than its numbers suggest and the repeat is from the body text. Only
+    /// the names before the repeat are used.
+    RepeatedName { name: String },
+}
+
+impl fmt::Display for ParseWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::CountMismatch { numbers, names } =>
+                write!(f, "found {} robot names for {} robot numbers", names, numbers),
+            Self::GroupTooLarge { numbers, max } =>
+                write!(f, "group of {} robot numbers exceeds maximum group size of {}", numbers, max),
+            Self::RepeatedName { name } =>
+                write!(f, "robot name {:?} appears more than once", name),
+        }
+    }
 }
 
 impl RobotName<'_> {
     /// Converts the robot's prefix from UTF-8 to ASCII and removes all non-alphanumeric characters.
     fn ident(&self) -> String {
-        let mut buf = unidecode(&self.prefix).to_lowercase();
-        buf.retain(|c| c.is_ascii_alphanumeric());
-        buf
+        normalise_ident(&self.prefix)
+    }
+}
+
+/// Converts a robot name to the form used in robot ids: ASCII, lowercase, with all non-alphanumeric
+/// characters removed.
+pub fn normalise_ident(name: &str) -> String {
+    let mut buf = unidecode(name).to_lowercase();
+    buf.retain(|c| c.is_ascii_alphanumeric());
+    buf
+}
+
+/// Describes why a robot tweet could not be parsed, and where in the tweet text the problem was
+/// found.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct ParseError {
+    pub kind: ParseErrorKind,
+
+    /// The byte offset into the tweet text at which the problem was found.
+    pub offset: usize,
+}
+
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub enum ParseErrorKind {
+    /// A content warning was opened with a square bracket which was never closed.
+    UnclosedContentWarning,
+    /// There was no closing parenthesis after the robot numbers.
+    MissingNumbersEnd,
+    /// A character other than a number or separator was found before the first robot number.
+    UnexpectedChar(char),
+    /// A robot number was too large to be stored.
+    NumberOutOfRange,
+    /// There were no robot numbers before the closing parenthesis.
+    NoNumbers,
+    /// No robot names were found after the robot numbers.
+    NoNames,
+}
+
+/// The stage of the parser at which a `ParseError` occurred.
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+pub enum ParseStage {
+    ContentWarning,
+    Numbers,
+    Names,
+}
+
+impl ParseError {
+    const fn new(kind: ParseErrorKind, offset: usize) -> Self {
+        Self {
+            kind,
+            offset,
+        }
+    }
+
+    pub fn stage(&self) -> ParseStage {
+        match self.kind {
+            ParseErrorKind::UnclosedContentWarning => ParseStage::ContentWarning,
+            ParseErrorKind::MissingNumbersEnd
+                | ParseErrorKind::UnexpectedChar(_)
+                | ParseErrorKind::NumberOutOfRange
+                | ParseErrorKind::NoNumbers => ParseStage::Numbers,
+            ParseErrorKind::NoNames => ParseStage::Names,
+        }
+    }
+
+    /// Returns a short excerpt of the given text, starting at the position where the error
+    /// occurred. The text should be the same text that was passed to `parse_group`.
+    pub fn context<'t>(&self, text: &'t str) -> &'t str {
+        const CONTEXT_CHARS: usize = 24;
+
+        let start = match text.get(self.offset..) {
+            Some(s) => s,
+            None => return "",
+        };
+
+        match start.char_indices().nth(CONTEXT_CHARS) {
+            Some((end, _)) => &start[..end],
+            None => start,
+        }
+    }
+
+    /// Moves the error's offset forward by the position of `sub` within `text`, where `sub` is a
+    /// substring of `text`.
+    fn relative_to(mut self, text: &str, sub: &str) -> Self {
+        self.offset += sub.as_ptr() as usize - text.as_ptr() as usize;
+        self
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at byte {}", self.kind, self.offset)
+    }
+}
+
+impl error::Error for ParseError {}
+
+impl fmt::Display for ParseErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnclosedContentWarning => write!(f, "unclosed content warning"),
+            Self::MissingNumbersEnd => write!(f, "missing \")\" after robot numbers"),
+            Self::UnexpectedChar(c) => write!(f, "unexpected character {:?} in robot numbers", c),
+            Self::NumberOutOfRange => write!(f, "robot number out of range"),
+            Self::NoNumbers => write!(f, "no robot numbers found"),
+            Self::NoNames => write!(f, "no robot names found"),
+        }
+    }
+}
+
+impl fmt::Display for ParseStage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::ContentWarning => write!(f, "content warning"),
+            Self::Numbers => write!(f, "robot numbers"),
+            Self::Names => write!(f, "robot names"),
+        }
     }
 }
 
@@ -87,9 +257,10 @@ impl<'a, T> ParseOut<'a, T> {
     }
 }
 
-pub fn parse_group(text: &str) -> Option<ParsedGroup> {
-    const MAX_GROUP_SIZE: usize = 5;
+/// The largest number of robots which may be parsed from a single tweet.
+pub const MAX_GROUP_SIZE: usize = 5;
 
+pub fn parse_group(text: &str) -> Result<ParsedGroup, ParseError> {
     lazy_static! {
         // Meaning                             | Regex fragment
         // =====================================================
@@ -102,21 +273,55 @@ pub fn parse_group(text: &str) -> Option<ParsedGroup> {
 
     let s = text.trim();
 
-    let ParseOut { remainder: s, output: cw }
-        = parse_cw(s);
+    let ParseOut { remainder: s, output: mut content_warnings }
+        = parse_cws(s, false)
+            .map_err(|err| err.relative_to(text, s))?;
 
     let ParseOut { remainder: s, output: n_range }
-        = parse_numbers(s)?;
+        = parse_numbers(s)
+            .map_err(|err| err.relative_to(text, s))?;
+
+    let ParseOut { remainder: names_s, output: late_content_warnings }
+        = parse_cws(s, true)
+            .map_err(|err| err.relative_to(text, s))?;
+
+    for cw in late_content_warnings {
+        if !content_warnings.contains(&cw) {
+            content_warnings.push(cw);
+        }
+    }
+
+    let mut warnings = Vec::new();
 
     let min_number = *n_range.start();
-    let num_numbers = (*n_range.end() - *n_range.start())
-        .checked_add(1)
-        .and_then(|n| usize::try_from(n).ok())
-        .map(|n| n.min(MAX_GROUP_SIZE))
-        .unwrap_or(MAX_GROUP_SIZE);
+    let range_size = usize::try_from(i64::from(*n_range.end()) - i64::from(min_number) + 1)
+        .unwrap_or(usize::MAX);
 
-    let ParseOut { remainder: s, output: (names, partial_names) }
-        = parse_names(s, num_numbers.min(MAX_GROUP_SIZE))?;
+    if range_size > MAX_GROUP_SIZE {
+        warnings.push(ParseWarning::GroupTooLarge {
+            numbers: range_size,
+            max: MAX_GROUP_SIZE,
+        });
+    }
+
+    let num_numbers = range_size.min(MAX_GROUP_SIZE);
+
+    let ParseOut { remainder: s, output: ParsedNames { names, partial, repeated } }
+        = parse_names(names_s, num_numbers.min(MAX_GROUP_SIZE))
+            .map_err(|err| err.relative_to(text, names_s))?;
+
+    if names.len() != num_numbers {
+        warnings.push(ParseWarning::CountMismatch {
+            numbers: num_numbers,
+            names: names.len(),
+        });
+    }
+
+    if let Some(name) = repeated {
+        warnings.push(ParseWarning::RepeatedName {
+            name: name.prefix.into_owned(),
+        });
+    }
 
     let body = BODY_RE
         .find(s)
@@ -128,7 +333,7 @@ pub fn parse_group(text: &str) -> Option<ParsedGroup> {
         .enumerate()
         .map(|(i, name)| Robot{
             number: min_number + (i as i32),
-            name: if partial_names {
+            name: if partial {
                 RobotName{ plural: None, ..name }
             } else {
                 name
@@ -136,58 +341,305 @@ pub fn parse_group(text: &str) -> Option<ParsedGroup> {
         })
         .collect::<Vec<Robot>>();
 
-    Some(ParsedGroup {
+    Ok(ParsedGroup {
         robots,
         body,
-        cw,
+        content_warnings,
+        warnings,
     })
 }
 
-fn parse_cw(s: &str) -> ParseOut<Option<&str>> {
+/// Parses any content warnings at the start of the string. Warnings may be bracketed, such as
+/// `[CW: spiders]` or `(TW: needles, blood)`, or given on a line of their own, such as
+/// `CN: spiders`. Before the robot numbers, any square-bracketed text is treated as a content
+/// warning; after the robot numbers (`after_numbers`), only text with a warning label is.
+fn parse_cws(s: &str, after_numbers: bool) -> Result<ParseOut<Vec<String>>, ParseError> {
+    const LABEL: &str = r"(?:c\.?w|t\.?w|c\.?n|content\s+warning|trigger\s+warning|content\s+note)s?\b";
+
     lazy_static! {
-        static ref CW_RE: Regex = Regex::new(r"^\s*[\[\(](.+:)?\W*(\S[^\]\)]+)[\]\)]").unwrap();
+        // Meaning                                      | Regex fragment
+        // ============================================================================
+        // Case insensitive                             | (?i)
+        // Beginning of the string                      |     ^
+        // Zero or more whitespace                      |      \s*
+        // Warning label, such as "CW" or "TW"          |         LABEL
+        // Zero or more whitespace                      |              \s*
+        // Optional colon or hyphen                     |                  [:\-]?
+        static ref LABEL_RE: Regex = Regex::new(&format!(r"(?i)^\s*{}\s*[:\-]?", LABEL)).unwrap();
+
+        // Meaning                                      | Regex fragment
+        // ============================================================================
+        // Case insensitive                             | (?i)
+        // Beginning of the string                      |     ^
+        // Warning label, such as "CW" or "TW"          |      LABEL
+        // Zero or more horizontal whitespace           |           [ \t]*
+        // Colon                                        |                 :
+        // First matching group                         |                  (     )
+        // One or more characters other than newline    |                   [^\n]+
+        // Newline                                      |                         \n
+        static ref LINE_RE: Regex = Regex::new(&format!(r"(?i)^{}[ \t]*:([^\n]+)\n", LABEL)).unwrap();
     }
 
-    let captures = match CW_RE.captures(s) {
-        Some(cs) => cs,
-        None     => return ParseOut::new(s, None),
-    };
+    let input = s;
+    let mut cws = Vec::new();
+    let mut s = s.trim_start();
 
-    let match_end = captures.get(0).unwrap().end();
-    let warning_type = captures.get(2).unwrap().as_str().trim();
+    loop {
+        let (content, remainder) = match s.chars().next() {
+            Some(open @ ('[' | '(')) => {
+                let close = if open == '[' { ']' } else { ')' };
+
+                let content_len = match s[1..].find(close) {
+                    Some(len) => len,
+                    None if open == '[' =>
+                        return Err(ParseError::new(ParseErrorKind::UnclosedContentWarning, 0)
+                            .relative_to(input, s)),
+                    None => break,
+                };
+
+                let content = &s[1..(1 + content_len)];
+                let remainder = &s[(2 + content_len)..];
+
+                let labelled = LABEL_RE.is_match(content);
+                let is_cw = labelled || match (open, after_numbers) {
+                    (_, true) => false,
+                    ('[', false) => true,
+                    _ => content.chars().any(char::is_alphabetic),
+                };
+
+                if !is_cw {
+                    break;
+                }
+
+                let content = match LABEL_RE.find(content) {
+                    Some(label) => &content[label.end()..],
+                    None => content
+                        .rsplit_once(':')
+                        .map(|(_, content)| content)
+                        .unwrap_or(content),
+                };
+
+                (content, remainder)
+            },
 
-    ParseOut::new(s[match_end..].trim_start(), Some(warning_type))
+            _ => match LINE_RE.captures(s) {
+                Some(caps) => (caps.get(1).unwrap().as_str(), &s[caps.get(0).unwrap().end()..]),
+                None => break,
+            },
+        };
+
+        for cw in content.split([',', ';', '/', '&']).filter_map(normalise_cw) {
+            if !cws.contains(&cw) {
+                cws.push(cw);
+            }
+        }
+
+        s = remainder.trim_start();
+    }
+
+    Ok(ParseOut::new(s, cws))
 }
 
-fn parse_numbers(s: &str) -> Option<ParseOut<RangeInclusive<i32>>> {
-    let (s, rem) = s
-        .split_once(')')?;
+/// Converts a content warning to the form it is stored in: lowercase, with surrounding punctuation
+/// removed and whitespace collapsed. Returns `None` if there is nothing left of the warning.
+pub fn normalise_cw(cw: &str) -> Option<String> {
+    let cw = cw.trim_matches(|c: char| !c.is_alphanumeric());
+
+    if cw.is_empty() {
+        return None;
+    }
+
+    let mut buf = String::with_capacity(cw.len());
+    for word in cw.split_whitespace() {
+        if !buf.is_empty() {
+            buf.push(' ');
+        }
+        buf.extend(word.chars().flat_map(char::to_lowercase));
+    }
+
+    Some(buf)
+}
+
+/// A syntax which may be used to write the robot numbers at the start of a robot tweet, such as
+/// `123)` or `#123`.
+struct NumberSyntax {
+    /// A regex which matches the robot numbers and any punctuation separating them from the rest of
+    /// the tweet. Its first capture group must contain only the list of numbers.
+    re: Regex,
+
+    /// Whether the numbers must be followed immediately by a robot name. Syntaxes which are easily
+    /// confused with years or numbered lists, such as `123.`, set this.
+    name_follows: bool,
+}
+
+impl NumberSyntax {
+    /// Creates a new number syntax from a regex pattern, where `{numbers}` in the pattern is replaced
+    /// with a capture group matching a list of robot numbers, such as `558/9` or `1039, 8 & 40`.
+    fn new(pattern: &str) -> Self {
+        // Meaning                                  | Regex fragment
+        // ===================================================================================
+        // First matching group                     | (                                     )
+        // Optional minus sign, one or more digits  |  -?\d+
+        // Zero or more of the following group      |       (?:                         )*
+        // Zero or more whitespace                  |          \s*
+        // Separator: comma, ampersand, slash,      |             (?:[,&/+\-]|and)
+        // plus, hyphen or "and"                    |
+        // Zero or more whitespace                  |                            \s*
+        // Optional minus sign, one or more digits  |                               -?\d+
+        const NUMBERS: &str = r"(-?\d+(?:\s*(?:[,&/+\-]|and)\s*-?\d+)*)";
+
+        Self {
+            re: Regex::new(&pattern.replace("{numbers}", NUMBERS)).unwrap(),
+            name_follows: false,
+        }
+    }
+
+    /// Only accept this syntax if the numbers are followed immediately by a robot name.
+    fn name_follows(mut self) -> Self {
+        self.name_follows = true;
+        self
+    }
+
+    /// Attempts to match this syntax at the start of the string, returning the parsed numbers and the
+    /// remainder of the string if successful.
+    fn parse<'a>(&self, s: &'a str) -> Result<Option<ParseOut<'a, RangeInclusive<i32>>>, ParseError> {
+        let caps = match self.re.captures(s) {
+            Some(caps) => caps,
+            None => return Ok(None),
+        };
+
+        let ns_match = caps.get(1).unwrap();
+        let ns = parse_number_list(ns_match.as_str())
+            .map_err(|err| err.relative_to(s, ns_match.as_str()))?;
+
+        let range = numbers_range(&ns)
+            .ok_or(ParseError::new(ParseErrorKind::NoNumbers, ns_match.start()))?;
+
+        let remainder = s[caps.get(0).unwrap().end()..].trim_start();
+
+        if self.name_follows && !starts_with_name(remainder) {
+            return Ok(None);
+        }
+
+        Ok(Some(ParseOut::new(remainder, range)))
+    }
+}
+
+/// Returns whether the string begins with a robot name such as `Teabot.` or the first part of a
+/// shared name such as `Salt-`, optionally after a bracketed content warning.
+fn starts_with_name(s: &str) -> bool {
+    lazy_static! {
+        // Meaning                                     | Regex fragment
+        // ==================================================================================
+        // Beginning of the string                     | ^
+        // Zero or more bracketed groups, such as      |  (?:[\[(][^\[\]()]*[\])]\s*)*
+        // content warnings, and whitespace            |
+        // One or more non-whitespace, followed by     |    (?:\S+[Bb][^\w\s]*[Oo][^\w\s]*[Tt]
+        // "bot" with optional punctuation between     |
+        // its letters                                 |
+        // Or 2 or more word characters, a hyphen and  |       |\w{2,}-(?:\s|$))
+        // whitespace or the end of the string         |
+        static ref NAME_START_RE: Regex = Regex::new(
+            r"^(?:[\[(][^\[\]()]*[\])]\s*)*(?:\S+[Bb][^\w\s]*[Oo][^\w\s]*[Tt]|\w{2,}-(?:\s|$))"
+        ).unwrap();
+    }
+
+    NAME_START_RE.is_match(Folded::new(s).as_str())
+}
+
+fn parse_numbers(s: &str) -> Result<ParseOut<RangeInclusive<i32>>, ParseError> {
+    lazy_static! {
+        /// The syntaxes that robot numbers may be written in, in order of preference.
+        static ref NUMBER_SYNTAXES: Vec<NumberSyntax> = vec![
+            // 123) or 558/9)
+            NumberSyntax::new(r"^\s*{numbers}\s*\)"),
+            // #123 or #558/9)
+            NumberSyntax::new(r"^\s*#\s*{numbers}\s*[.:)]?"),
+            // No. 123 or Nos. 558/9:
+            NumberSyntax::new(r"(?i)^\s*(?:no|nos|number)\.?\s*{numbers}\s*[.:)]?"),
+            // 123. Teabot or 123: Teabot
+            NumberSyntax::new(r"^\s*{numbers}\s*[.:](?:\s|$)").name_follows(),
+        ];
+
+        // Meaning                                     | Regex fragment
+        // ==================================================================================
+        // Case insensitive                            | (?i)
+        // Beginning of the string                     |     ^
+        // Zero or more whitespace                     |      \s*
+        // A repost marker, such as "RT", "Repost",    |         (?:rt|re-?post(?:ed)?|...)
+        // "Throwback Thursday" or "From the archives" |
+        // Word boundary                               |                                   \b
+        // Zero or more whitespace, colons,            |                                     [\s:!.\-]*
+        // exclamation marks, full stops or hyphens    |
+        static ref REPOST_PREFIX_RE: Regex = Regex::new(
+            r"(?i)^\s*(?:rt|re-?post(?:ed)?|throwback(?:\s+thursday)?|tbt|from\s+the\s+archives?|encore)\b[\s:!.\-]*"
+        ).unwrap();
+    }
+
+    for syntax in NUMBER_SYNTAXES.iter() {
+        if let Some(out) = syntax.parse(s)? {
+            return Ok(out);
+        }
+    }
+
+    // Reposts sometimes put a marker before the robot numbers, such as "Repost! 123) Teabot", so
+    // try again after skipping it. Only known markers are skipped, so that other tweets which
+    // happen to contain numbers, such as "Reminder: 2. ..." are not taken for robots.
+    if let Some(prefix) = REPOST_PREFIX_RE.find(s) {
+        let unprefixed = &s[prefix.end()..];
+        for syntax in NUMBER_SYNTAXES.iter() {
+            if let Some(out) = syntax.parse(unprefixed).map_err(|err| err.relative_to(s, unprefixed))? {
+                return Ok(out);
+            }
+        }
+    }
+
+    // Fall back to the most lenient syntax, where anything after the first number and before the
+    // closing parenthesis is ignored
+    let (ns_s, rem) = s
+        .split_once(')')
+        .ok_or(ParseError::new(ParseErrorKind::MissingNumbersEnd, 0))?;
+
+    let ns = parse_number_list(ns_s)?;
 
+    let range = numbers_range(&ns)
+        .ok_or(ParseError::new(ParseErrorKind::NoNumbers, ns_s.len() - ns_s.trim_start().len()))?;
+
+    Ok(ParseOut::new(rem.trim_start(), range))
+}
+
+/// Parses a list of robot numbers, such as `"1039, 8 & 40"`. Any characters after the first number
+/// which are not digits or minus signs are treated as separators.
+fn parse_number_list(s: &str) -> Result<Vec<i32>, ParseError> {
+    let start = s.len() - s.trim_start().len();
     let s = s.trim();
-    let rem = rem.trim_start();
 
     let mut ns = Vec::<i32>::new();
 
     let mut buf = String::new();
+    let mut buf_start = 0;
     let mut neg = false;
     let mut neg_enabled = true;
     let mut found_digit = false;
 
-    fn parse_number(buf: &str, neg: bool) -> Option<i32> {
+    fn parse_number(buf: &str, neg: bool, offset: usize) -> Result<i32, ParseError> {
         buf
             .parse::<i32>()
-            .ok()
             .map(|n| n * if neg { -1 } else { 1 })
+            .map_err(|_| ParseError::new(ParseErrorKind::NumberOutOfRange, offset))
     }
 
-    for c in s.chars() {
+    for (i, c) in s.char_indices() {
         if c.is_ascii_digit() {
+            if buf.is_empty() {
+                buf_start = i;
+            }
             found_digit = true;
             neg_enabled = false;
             buf.push(c);
         } else {
             if !buf.is_empty() {
-                ns.push(parse_number(&buf, neg)?);
+                ns.push(parse_number(&buf, neg, start + buf_start)?);
                 buf.clear();
             }
             if c == '-' {
@@ -198,17 +650,17 @@ fn parse_numbers(s: &str) -> Option<ParseOut<RangeInclusive<i32>>> {
                 neg = false;
                 neg_enabled = true;
                 if !found_digit {
-                    return None;
+                    return Err(ParseError::new(ParseErrorKind::UnexpectedChar(c), start + i));
                 }
             }
         }
     }
 
     if !buf.is_empty() {
-        ns.push(parse_number(&buf, neg)?);
+        ns.push(parse_number(&buf, neg, start + buf_start)?);
     }
 
-    Some(ParseOut::new(rem, numbers_range(&ns)?))
+    Ok(ns)
 }
 
 fn numbers_range(ns: &[i32]) -> Option<RangeInclusive<i32>> {
@@ -225,8 +677,11 @@ fn numbers_range(ns: &[i32]) -> Option<RangeInclusive<i32>> {
     let (mut min_n, mut max_n) = (first, first);
 
     for &n in &ns[1..] {
-        let n = if n > 0 && n < first.abs() {
-            let mut major = first;
+        // A number smaller than the first is treated as an abbreviation which replaces the first
+        // number's trailing digits, e.g. 558/9 is 558/559. If the expanded number would not fit in
+        // an i32, the number is used as-is instead.
+        let n = if n > 0 && n.unsigned_abs() < first.unsigned_abs() {
+            let mut major = i64::from(first);
             let mut dps = 0;
             let mut x = n;
             while x > 0 {
@@ -237,7 +692,8 @@ fn numbers_range(ns: &[i32]) -> Option<RangeInclusive<i32>> {
             for _ in 0..dps {
                 major *= 10;
             }
-            major + (n * first.signum())
+            i32::try_from(major + i64::from(n) * i64::from(first.signum()))
+                .unwrap_or(n)
         } else {
             n
         };
@@ -252,7 +708,20 @@ fn numbers_range(ns: &[i32]) -> Option<RangeInclusive<i32>> {
     Some(min_n..=max_n)
 }
 
-fn parse_names(s: &str, target_n: usize) -> Option<ParseOut<(Vec<RobotName>, bool)>> {
+/// The robot names found by `parse_names`.
+#[derive(PartialEq, Eq, Debug)]
+struct ParsedNames<'a> {
+    names: Vec<RobotName<'a>>,
+    /// Whether some of the names were completed from partial names, such as "Salt-" in
+    /// "Salt- and Pepperbots".
+    partial: bool,
+    /// The first name which was found again, if any. Names are only taken up to a repeated name,
+    /// since it must come from the body text.
+    repeated: Option<RobotName<'a>>,
+}
+
+/// Parses up to `target_n` robot names.
+fn parse_names(s: &str, target_n: usize) -> Result<ParseOut<ParsedNames>, ParseError> {
     lazy_static! {
         // Meaning                            | Regex fragment
         // =======================================================================================
@@ -280,31 +749,43 @@ fn parse_names(s: &str, target_n: usize) -> Option<ParseOut<(Vec<RobotName>, boo
     }
 
     let mut names = Vec::<RobotName>::new();
+    let mut repeated_name = None;
     let mut first_match = true;
     let mut matches_start = 0;
     let mut matches_end = 0;
 
-    for caps in BOT_RE.captures_iter(s) {
+    // Match against a folded copy of the string so that stylised names such as "Ｔｅａｂｏｔ" are
+    // found, but take the name components from the original string to keep their spelling
+    let folded = Folded::new(s);
+
+    for caps in BOT_RE.captures_iter(folded.as_str()) {
         if names.len() == target_n {
             break;
         }
 
-        names.push(RobotName{
-            prefix: Cow::Borrowed(caps.get(1).unwrap().as_str()),
-            suffix: Cow::Borrowed(caps.get(2).unwrap().as_str()),
-            plural: caps.get(3).map(|m| Cow::Borrowed(m.as_str())),
-        });
+        let name = RobotName{
+            prefix: Cow::Borrowed(folded.original_slice(caps.get(1).unwrap().range())),
+            suffix: Cow::Borrowed(folded.original_slice(caps.get(2).unwrap().range())),
+            plural: caps.get(3).map(|m| Cow::Borrowed(folded.original_slice(m.range()))),
+        };
+
+        if names.iter().any(|prev| prev.ident() == name.ident()) {
+            repeated_name = Some(name);
+            break;
+        }
+
+        names.push(name);
 
-        let full_match = caps.get(0).unwrap();
+        let full_match = folded.original_range(caps.get(0).unwrap().range());
         if first_match {
             first_match = false;
-            matches_start = full_match.start();
+            matches_start = full_match.start;
         }
-        matches_end = full_match.end();
+        matches_end = full_match.end;
     }
 
     if names.is_empty() {
-        return None;
+        return Err(ParseError::new(ParseErrorKind::NoNames, 0));
     }
 
     let use_partial_names = names.len() < target_n && matches_start > 0;
@@ -333,42 +814,122 @@ fn parse_names(s: &str, target_n: usize) -> Option<ParseOut<(Vec<RobotName>, boo
         }
     }
 
-    Some(ParseOut::new(&s[matches_end..], (names, use_partial_names)))
+    Ok(ParseOut::new(&s[matches_end..], ParsedNames {
+        names,
+        partial: use_partial_names,
+        repeated: repeated_name,
+    }))
+}
+
+/// Checks the properties which should hold for the result of `parse_group` on any input, panicking
+/// if any do not. Shared by the property tests and the fuzz target.
+#[cfg(any(test, fuzzing))]
+#[doc(hidden)]
+pub fn check_group_invariants(text: &str) {
+    let group = match parse_group(text) {
+        Ok(group) => group,
+        Err(err) => {
+            assert!(err.offset <= text.len(), "error offset {} out of bounds", err.offset);
+            err.context(text);
+            return;
+        },
+    };
+
+    assert!(!group.robots.is_empty());
+    assert!(group.robots.len() <= MAX_GROUP_SIZE);
+
+    let first_number = group.robots[0].number;
+    for (i, robot) in group.robots.iter().enumerate() {
+        assert_eq!(i64::from(robot.number), i64::from(first_number) + i as i64);
+        robot.ident();
+    }
+
+    let text_start = text.as_ptr() as usize;
+    let body_start = group.body.as_ptr() as usize;
+    assert!(
+        group.body.is_empty()
+            || (body_start >= text_start && body_start + group.body.len() <= text_start + text.len()),
+        "body {:?} is not a substring of the input",
+        group.body
+    );
 }
 
 #[cfg(test)]
 mod tests {
-    use super::{ParseOut, ParsedGroup, RobotName};
+    use super::{check_group_invariants, ParseError, ParseErrorKind, ParseOut, ParseWarning, ParsedGroup, ParsedNames, RobotName};
 
     #[test]
     fn test_parse_numbers() {
         use super::parse_numbers;
 
-        assert_eq!(parse_numbers("123)"), Some(ParseOut::new("", 123..=123)));
-        assert_eq!(parse_numbers("123) Teabot"), Some(ParseOut::new("Teabot", 123..=123)));
-        assert_eq!(parse_numbers("  123  )  Teabot  "), Some(ParseOut::new("Teabot  ", 123..=123)));
-        assert_eq!(parse_numbers("-1)"), Some(ParseOut::new("", -1..=-1)));
-        assert_eq!(parse_numbers("1, 2, 3)"), Some(ParseOut::new("", 1..=3)));
-        assert_eq!(parse_numbers("123-124)"), Some(ParseOut::new("", 123..=124)));
-        assert_eq!(parse_numbers("123 - 124)"), Some(ParseOut::new("", 123..=124)));
-        assert_eq!(parse_numbers("123 & 4)"), Some(ParseOut::new("", 123..=124)));
-        assert_eq!(parse_numbers("123 & 24)"), Some(ParseOut::new("", 123..=124)));
-        assert_eq!(parse_numbers("124 & 3)"), Some(ParseOut::new("", 123..=124)));
-        assert_eq!(parse_numbers("8, 7)"), Some(ParseOut::new("", 7..=8)));
-        assert_eq!(parse_numbers("124-123)"), Some(ParseOut::new("", 123..=124)));
-        assert_eq!(parse_numbers("1024 - 1048)"), Some(ParseOut::new("", 1024..=1048)));
-        assert_eq!(parse_numbers("1024, 5 & 6)"), Some(ParseOut::new("", 1024..=1026)));
-        assert_eq!(parse_numbers("1039, 8 & 40)"), Some(ParseOut::new("", 1038..=1040)));
-        assert_eq!(parse_numbers("123"), None);
-        assert_eq!(parse_numbers("Foo baa"), None);
-        assert_eq!(parse_numbers("2147483646)"), Some(ParseOut::new("", 2147483646..=2147483646)));
-        assert_eq!(parse_numbers("2147483647)"), Some(ParseOut::new("", 2147483647..=2147483647)));
-        assert_eq!(parse_numbers("2147483648)"), None);
-        assert_eq!(parse_numbers("2147483646 - 2147483647)"), Some(ParseOut::new("", 2147483646..=2147483647)));
-        assert_eq!(parse_numbers("2147483646 - 2147483648)"), None);
-        assert_eq!(parse_numbers("Hello)"), None);
-        assert_eq!(parse_numbers("@foo 123)"), None);
-        assert_eq!(parse_numbers("@foo123)"), None);
+        assert_eq!(parse_numbers("123)"), Ok(ParseOut::new("", 123..=123)));
+        assert_eq!(parse_numbers("123) Teabot"), Ok(ParseOut::new("Teabot", 123..=123)));
+        assert_eq!(parse_numbers("  123  )  Teabot  "), Ok(ParseOut::new("Teabot  ", 123..=123)));
+        assert_eq!(parse_numbers("-1)"), Ok(ParseOut::new("", -1..=-1)));
+        assert_eq!(parse_numbers("1, 2, 3)"), Ok(ParseOut::new("", 1..=3)));
+        assert_eq!(parse_numbers("123-124)"), Ok(ParseOut::new("", 123..=124)));
+        assert_eq!(parse_numbers("123 - 124)"), Ok(ParseOut::new("", 123..=124)));
+        assert_eq!(parse_numbers("123 & 4)"), Ok(ParseOut::new("", 123..=124)));
+        assert_eq!(parse_numbers("123 & 24)"), Ok(ParseOut::new("", 123..=124)));
+        assert_eq!(parse_numbers("124 & 3)"), Ok(ParseOut::new("", 123..=124)));
+        assert_eq!(parse_numbers("8, 7)"), Ok(ParseOut::new("", 7..=8)));
+        assert_eq!(parse_numbers("124-123)"), Ok(ParseOut::new("", 123..=124)));
+        assert_eq!(parse_numbers("1024 - 1048)"), Ok(ParseOut::new("", 1024..=1048)));
+        assert_eq!(parse_numbers("1024, 5 & 6)"), Ok(ParseOut::new("", 1024..=1026)));
+        assert_eq!(parse_numbers("1039, 8 & 40)"), Ok(ParseOut::new("", 1038..=1040)));
+        assert_eq!(parse_numbers("123"), Err(ParseError { kind: ParseErrorKind::MissingNumbersEnd, offset: 0 }));
+        assert_eq!(parse_numbers("Foo baa"), Err(ParseError { kind: ParseErrorKind::MissingNumbersEnd, offset: 0 }));
+        assert_eq!(parse_numbers("2147483646)"), Ok(ParseOut::new("", 2147483646..=2147483646)));
+        assert_eq!(parse_numbers("2147483647)"), Ok(ParseOut::new("", 2147483647..=2147483647)));
+        assert_eq!(parse_numbers("2147483648)"), Err(ParseError { kind: ParseErrorKind::NumberOutOfRange, offset: 0 }));
+        assert_eq!(parse_numbers("2147483646 - 2147483647)"), Ok(ParseOut::new("", 2147483646..=2147483647)));
+        assert_eq!(parse_numbers("2147483646 - 2147483648)"), Err(ParseError { kind: ParseErrorKind::NumberOutOfRange, offset: 13 }));
+        assert_eq!(parse_numbers("Hello)"), Err(ParseError { kind: ParseErrorKind::UnexpectedChar('H'), offset: 0 }));
+        assert_eq!(parse_numbers("@foo 123)"), Err(ParseError { kind: ParseErrorKind::UnexpectedChar('@'), offset: 0 }));
+        assert_eq!(parse_numbers("@foo123)"), Err(ParseError { kind: ParseErrorKind::UnexpectedChar('@'), offset: 0 }));
+        assert_eq!(parse_numbers("  )"), Err(ParseError { kind: ParseErrorKind::NoNumbers, offset: 2 }));
+        assert_eq!(parse_numbers("#123 Teabot"), Ok(ParseOut::new("Teabot", 123..=123)));
+        assert_eq!(parse_numbers("#558/9 Salt- and Pepperbots"), Ok(ParseOut::new("Salt- and Pepperbots", 558..=559)));
+        assert_eq!(parse_numbers("# 123: Teabot"), Ok(ParseOut::new("Teabot", 123..=123)));
+        assert_eq!(parse_numbers("No. 123 Teabot"), Ok(ParseOut::new("Teabot", 123..=123)));
+        assert_eq!(parse_numbers("nos.558 & 559. Salt- and Pepperbots"), Ok(ParseOut::new("Salt- and Pepperbots", 558..=559)));
+        assert_eq!(parse_numbers("123. Teabot"), Ok(ParseOut::new("Teabot", 123..=123)));
+        assert_eq!(parse_numbers("123: Teabot"), Ok(ParseOut::new("Teabot", 123..=123)));
+        assert_eq!(parse_numbers("123. Teabot. Brings you tea (and 2 biscuits)"), Ok(ParseOut::new("Teabot. Brings you tea (and 2 biscuits)", 123..=123)));
+        assert_eq!(parse_numbers("Repost! 123) Teabot"), Ok(ParseOut::new("Teabot", 123..=123)));
+        assert_eq!(parse_numbers("From the archives: #123 Teabot"), Ok(ParseOut::new("Teabot", 123..=123)));
+        assert_eq!(parse_numbers("Throwback Thursday!\n1000. Fireworkbot"), Ok(ParseOut::new("Fireworkbot", 1000..=1000)));
+        assert_eq!(parse_numbers("RT: 558/9: Salt- and Pepperbots"), Ok(ParseOut::new("Salt- and Pepperbots", 558..=559)));
+        assert_eq!(parse_numbers("123. [CW: spiders] Spiderbot"), Ok(ParseOut::new("[CW: spiders] Spiderbot", 123..=123)));
+        assert_eq!(parse_numbers("Happy new year!\n1000. Fireworkbot"), Err(ParseError { kind: ParseErrorKind::MissingNumbersEnd, offset: 0 }));
+        assert_eq!(parse_numbers("Reminder: 2. Vote for Teabot"), Err(ParseError { kind: ParseErrorKind::MissingNumbersEnd, offset: 0 }));
+        assert_eq!(parse_numbers("2022: the year of Teabot"), Err(ParseError { kind: ParseErrorKind::MissingNumbersEnd, offset: 0 }));
+        assert_eq!(parse_numbers("2. Don't forget about Teabot"), Err(ParseError { kind: ParseErrorKind::MissingNumbersEnd, offset: 0 }));
+        assert_eq!(parse_numbers("Repost! 2147483648) Bigbot"), Err(ParseError { kind: ParseErrorKind::NumberOutOfRange, offset: 8 }));
+        assert_eq!(parse_numbers("Nobot. Is not a robot"), Err(ParseError { kind: ParseErrorKind::MissingNumbersEnd, offset: 0 }));
+    }
+
+    #[test]
+    fn test_parse_cws() {
+        use super::parse_cws;
+
+        fn cws(cws: &[&str]) -> Vec<String> {
+            cws.iter().map(|cw| (*cw).to_owned()).collect()
+        }
+
+        assert_eq!(parse_cws("123) Teabot", false), Ok(ParseOut::new("123) Teabot", cws(&[]))));
+        assert_eq!(parse_cws("[CW: spiders] 8) Spiderbot", false), Ok(ParseOut::new("8) Spiderbot", cws(&["spiders"]))));
+        assert_eq!(parse_cws("[sexual assault] 651)", false), Ok(ParseOut::new("651)", cws(&["sexual assault"]))));
+        assert_eq!(parse_cws("(TW: Needles) 9)", false), Ok(ParseOut::new("9)", cws(&["needles"]))));
+        assert_eq!(parse_cws("[Content Warning - Spiders, Blood / NEEDLES] 8)", false), Ok(ParseOut::new("8)", cws(&["spiders", "blood", "needles"]))));
+        assert_eq!(parse_cws("[CW: spiders] [TW: needles; spiders] 8)", false), Ok(ParseOut::new("8)", cws(&["spiders", "needles"]))));
+        assert_eq!(parse_cws("TW: death\n8) Ghostbot", false), Ok(ParseOut::new("8) Ghostbot", cws(&["death"]))));
+        assert_eq!(parse_cws("TW: death 8) Ghostbot", false), Ok(ParseOut::new("TW: death 8) Ghostbot", cws(&[]))));
+        assert_eq!(parse_cws("(12) Teabot", false), Ok(ParseOut::new("(12) Teabot", cws(&[]))));
+        assert_eq!(parse_cws("[CW: spiders 8) Spiderbot", false), Err(ParseError { kind: ParseErrorKind::UnclosedContentWarning, offset: 0 }));
+        assert_eq!(parse_cws("[CN: spiders] Spiderbot", true), Ok(ParseOut::new("Spiderbot", cws(&["spiders"]))));
+        assert_eq!(parse_cws("[spiders] Spiderbot", true), Ok(ParseOut::new("[spiders] Spiderbot", cws(&[]))));
+        assert_eq!(parse_cws("Twobots. Are two robots", true), Ok(ParseOut::new("Twobots. Are two robots", cws(&[]))));
     }
 
     #[test]
@@ -377,37 +938,62 @@ mod tests {
 
         assert_eq!(
             parse_names("Teabot. Brings you tea", 1),
-            Some(ParseOut::new(". Brings you tea", (vec![RobotName{ prefix: "Tea".into(), suffix: "bot".into(), plural: None }], false)))
+            Ok(ParseOut::new(". Brings you tea", ParsedNames { names: vec![RobotName{ prefix: "Tea".into(), suffix: "bot".into(), plural: None }], partial: false, repeated: None }))
         );
         
         assert_eq!(
             parse_names("Mischiefbots. Oh no!!", 1),
-            Some(ParseOut::new(". Oh no!!", (vec![RobotName{ prefix: "Mischief".into(), suffix: "bot".into(), plural: Some("s".into()) }], false)))
+            Ok(ParseOut::new(". Oh no!!", ParsedNames { names: vec![RobotName{ prefix: "Mischief".into(), suffix: "bot".into(), plural: Some("s".into()) }], partial: false, repeated: None }))
         );
         
         assert_eq!(
             parse_names("R.O.B.O.T.S.", 1),
-            Some(ParseOut::new(".", (vec![RobotName{ prefix: "R.O.".into(), suffix: "B.O.T".into(), plural: Some(".S".into()) }], false)))
+            Ok(ParseOut::new(".", ParsedNames { names: vec![RobotName{ prefix: "R.O.".into(), suffix: "B.O.T".into(), plural: Some(".S".into()) }], partial: false, repeated: None }))
         );
         
         assert_eq!(
             parse_names("Saltbot and pepperbot.", 1),
-            Some(ParseOut::new(" and pepperbot.", (vec![RobotName{ prefix: "Salt".into(), suffix: "bot".into(), plural: None }], false)))
+            Ok(ParseOut::new(" and pepperbot.", ParsedNames { names: vec![RobotName{ prefix: "Salt".into(), suffix: "bot".into(), plural: None }], partial: false, repeated: None }))
         );
         
         assert_eq!(
             parse_names("Saltbot and pepperbot.", 2),
-            Some(ParseOut::new(".", (vec![RobotName{ prefix: "Salt".into(), suffix: "bot".into(), plural: None }, RobotName{ prefix: "pepper".into(), suffix: "bot".into(), plural: None }], false)))
+            Ok(ParseOut::new(".", ParsedNames { names: vec![RobotName{ prefix: "Salt".into(), suffix: "bot".into(), plural: None }, RobotName{ prefix: "pepper".into(), suffix: "bot".into(), plural: None }], partial: false, repeated: None }))
         );
         
         assert_eq!(
             parse_names("Saltbot and pepperbot.", 3),
-            Some(ParseOut::new(".", (vec![RobotName{ prefix: "Salt".into(), suffix: "bot".into(), plural: None }, RobotName{ prefix: "pepper".into(), suffix: "bot".into(), plural: None }], false)))
+            Ok(ParseOut::new(".", ParsedNames { names: vec![RobotName{ prefix: "Salt".into(), suffix: "bot".into(), plural: None }, RobotName{ prefix: "pepper".into(), suffix: "bot".into(), plural: None }], partial: false, repeated: None }))
         );
         
         assert_eq!(
             parse_names("Salt- and pepperbots.", 2),
-            Some(ParseOut::new(".", (vec![RobotName{ prefix: "Salt".into(), suffix: "bot".into(), plural: Some("s".into()) }, RobotName{ prefix: "pepper".into(), suffix: "bot".into(), plural: Some("s".into()) }], true)))
+            Ok(ParseOut::new(".", ParsedNames { names: vec![RobotName{ prefix: "Salt".into(), suffix: "bot".into(), plural: Some("s".into()) }, RobotName{ prefix: "pepper".into(), suffix: "bot".into(), plural: Some("s".into()) }], partial: true, repeated: None }))
+        );
+
+        assert_eq!(
+            parse_names("Ｔｅａｂｏｔ． Brings you tea", 1),
+            Ok(ParseOut::new("． Brings you tea", ParsedNames { names: vec![RobotName{ prefix: "Ｔｅａ".into(), suffix: "ｂｏｔ".into(), plural: None }], partial: false, repeated: None }))
+        );
+
+        assert_eq!(
+            parse_names("Crème brûléeBÖTS. Oh la la", 1),
+            Ok(ParseOut::new(". Oh la la", ParsedNames { names: vec![RobotName{ prefix: "brûlée".into(), suffix: "BÖT".into(), plural: Some("S".into()) }], partial: false, repeated: None }))
+        );
+
+        assert_eq!(
+            parse_names("Sneakyb\u{200d}o\u{200d}t. Hides", 1),
+            Ok(ParseOut::new(". Hides", ParsedNames { names: vec![RobotName{ prefix: "Sneaky".into(), suffix: "b\u{200d}o\u{200d}t".into(), plural: None }], partial: false, repeated: None }))
+        );
+
+        assert_eq!(
+            parse_names("SpyВОТ. Is a spy", 1),
+            Ok(ParseOut::new(". Is a spy", ParsedNames { names: vec![RobotName{ prefix: "Spy".into(), suffix: "ВОТ".into(), plural: None }], partial: false, repeated: None }))
+        );
+
+        assert_eq!(
+            parse_names("Marybot and Josephbot. Marybot gets a package.", 3),
+            Ok(ParseOut::new(". Marybot gets a package.", ParsedNames { names: vec![RobotName{ prefix: "Mary".into(), suffix: "bot".into(), plural: None }, RobotName{ prefix: "Joseph".into(), suffix: "bot".into(), plural: None }], partial: false, repeated: Some(RobotName{ prefix: "Mary".into(), suffix: "bot".into(), plural: None }) }))
         );
     }
 
@@ -417,22 +1003,254 @@ mod tests {
 
         assert_eq!(
             parse_group("1207) Transrightsbot. Is just here to let all its trans pals know that they are valid and they are loved! \u{1f3f3}\u{fe0f}\u{200d}\u{26a7}\u{fe0f}\u{2764}\u{fe0f}\u{1f916}"),
-            Some(ParsedGroup { robots: vec![Robot { number: 1207, name: RobotName { prefix: "Transrights".into(), suffix: "bot".into(), plural: None } }], body: "Is just here to let all its trans pals know that they are valid and they are loved! \u{1f3f3}\u{fe0f}\u{200d}\u{26a7}\u{fe0f}\u{2764}\u{fe0f}\u{1f916}", cw: None })
+            Ok(ParsedGroup { robots: vec![Robot { number: 1207, name: RobotName { prefix: "Transrights".into(), suffix: "bot".into(), plural: None } }], body: "Is just here to let all its trans pals know that they are valid and they are loved! \u{1f3f3}\u{fe0f}\u{200d}\u{26a7}\u{fe0f}\u{2764}\u{fe0f}\u{1f916}", content_warnings: vec![], warnings: vec![] })
         );
         
         assert_eq!(
             parse_group("558/9) Salt- and Pepperbots. Bring you salt and pepper."),
-            Some(ParsedGroup { robots: vec![Robot { number: 558, name: RobotName { prefix: "Salt".into(), suffix: "bot".into(), plural: None } }, Robot { number: 559, name: RobotName { prefix: "Pepper".into(), suffix: "bot".into(), plural: None } }], body: "Bring you salt and pepper.", cw: None })
+            Ok(ParsedGroup { robots: vec![Robot { number: 558, name: RobotName { prefix: "Salt".into(), suffix: "bot".into(), plural: None } }, Robot { number: 559, name: RobotName { prefix: "Pepper".into(), suffix: "bot".into(), plural: None } }], body: "Bring you salt and pepper.", content_warnings: vec![], warnings: vec![] })
         );
         
         assert_eq!(
             parse_group("690 - 692) Marybot, Josephbot and Donkeybot. For complicated tax reasons, Marybot and Josephbot are forced to temporarily relocate to Bethlehem, just as Marybot recieves a mysterious package from Gabrielbot on behalf of Godbot Labs."),
-            Some(ParsedGroup { robots: vec![Robot { number: 690, name: RobotName { prefix: "Mary".into(), suffix: "bot".into(), plural: None } }, Robot { number: 691, name: RobotName { prefix: "Joseph".into(), suffix: "bot".into(), plural: None } }, Robot { number: 692, name: RobotName { prefix: "Donkey".into(), suffix: "bot".into(), plural: None } }], body: "For complicated tax reasons, Marybot and Josephbot are forced to temporarily relocate to Bethlehem, just as Marybot recieves a mysterious package from Gabrielbot on behalf of Godbot Labs.", cw: None })
+            Ok(ParsedGroup { robots: vec![Robot { number: 690, name: RobotName { prefix: "Mary".into(), suffix: "bot".into(), plural: None } }, Robot { number: 691, name: RobotName { prefix: "Joseph".into(), suffix: "bot".into(), plural: None } }, Robot { number: 692, name: RobotName { prefix: "Donkey".into(), suffix: "bot".into(), plural: None } }], body: "For complicated tax reasons, Marybot and Josephbot are forced to temporarily relocate to Bethlehem, just as Marybot recieves a mysterious package from Gabrielbot on behalf of Godbot Labs.", content_warnings: vec![], warnings: vec![] })
         );
         
         assert_eq!(
             parse_group("[CN: sexual assault] 651) Believeherbot. Reminds you to believe the testimony of women survivors of sexual assault; reminds you to look at the gendered power structures in place before you dismiss them as unreliable; reminds you that this is the fucking turning point."),
-            Some(ParsedGroup { robots: vec![Robot { number: 651, name: RobotName { prefix: "Believeher".into(), suffix: "bot".into(), plural: None } }], body: "Reminds you to believe the testimony of women survivors of sexual assault; reminds you to look at the gendered power structures in place before you dismiss them as unreliable; reminds you that this is the fucking turning point.", cw: Some("sexual assault") })
+            Ok(ParsedGroup { robots: vec![Robot { number: 651, name: RobotName { prefix: "Believeher".into(), suffix: "bot".into(), plural: None } }], body: "Reminds you to believe the testimony of women survivors of sexual assault; reminds you to look at the gendered power structures in place before you dismiss them as unreliable; reminds you that this is the fucking turning point.", content_warnings: vec!["sexual assault".to_owned()], warnings: vec![] })
         );
+
+        assert_eq!(
+            parse_group("[TW: spiders] 8) (CW: needles) Spiderbot. Gently escorts spiders out of your house."),
+            Ok(ParsedGroup { robots: vec![Robot { number: 8, name: RobotName { prefix: "Spider".into(), suffix: "bot".into(), plural: None } }], body: "Gently escorts spiders out of your house.", content_warnings: vec!["spiders".to_owned(), "needles".to_owned()], warnings: vec![] })
+        );
+
+        assert_eq!(
+            parse_group("  Hello, world!"),
+            Err(ParseError { kind: ParseErrorKind::MissingNumbersEnd, offset: 2 })
+        );
+
+        assert_eq!(
+            parse_group("[CW: spiders 123. Spiderbot. Is a spider."),
+            Err(ParseError { kind: ParseErrorKind::UnclosedContentWarning, offset: 0 })
+        );
+
+        assert_eq!(
+            parse_group("[CW: spiders] x12) Spiderbot. Is a spider."),
+            Err(ParseError { kind: ParseErrorKind::UnexpectedChar('x'), offset: 14 })
+        );
+
+        assert_eq!(
+            parse_group("123) Hello. Nothing to see here."),
+            Err(ParseError { kind: ParseErrorKind::NoNames, offset: 5 })
+        );
+
+        assert_eq!(
+            parse_group("690 - 692) Marybot and Josephbot. Go to Bethlehem."),
+            Ok(ParsedGroup { robots: vec![Robot { number: 690, name: RobotName { prefix: "Mary".into(), suffix: "bot".into(), plural: None } }, Robot { number: 691, name: RobotName { prefix: "Joseph".into(), suffix: "bot".into(), plural: None } }], body: "Go to Bethlehem.", content_warnings: vec![], warnings: vec![ParseWarning::CountMismatch { numbers: 3, names: 2 }] })
+        );
+
+        assert_eq!(
+            parse_group("690 - 692) Marybot and Josephbot. Marybot gets a package."),
+            Ok(ParsedGroup { robots: vec![Robot { number: 690, name: RobotName { prefix: "Mary".into(), suffix: "bot".into(), plural: None } }, Robot { number: 691, name: RobotName { prefix: "Joseph".into(), suffix: "bot".into(), plural: None } }], body: "Marybot gets a package.", content_warnings: vec![], warnings: vec![ParseWarning::CountMismatch { numbers: 3, names: 2 }, ParseWarning::RepeatedName { name: "Mary".to_owned() }] })
+        );
+
+        assert_eq!(
+            parse_group("1 - 10) Teabots. Bring you lots of tea."),
+            Ok(ParsedGroup { robots: vec![Robot { number: 1, name: RobotName { prefix: "Tea".into(), suffix: "bot".into(), plural: Some("s".into()) } }], body: "Bring you lots of tea.", content_warnings: vec![], warnings: vec![ParseWarning::GroupTooLarge { numbers: 10, max: 5 }, ParseWarning::CountMismatch { numbers: 5, names: 1 }] })
+        );
+    }
+
+    /// Runs `parse_group` over every `.txt` file in the `parse_corpus` directory and compares the
+    /// result against the `.json` file with the same name. If the `SBB_UPDATE_CORPUS` environment
+    /// variable is set, the `.json` files are regenerated from the current parser output instead.
+    #[test]
+    fn test_parse_corpus() {
+        use std::env;
+        use std::fs;
+        use std::path::Path;
+
+        use super::parse_group;
+
+        let corpus_dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("parse_corpus");
+        let update = env::var_os("SBB_UPDATE_CORPUS").is_some();
+
+        let mut text_paths = fs::read_dir(&corpus_dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| path.extension().is_some_and(|ext| ext == "txt"))
+            .collect::<Vec<_>>();
+
+        text_paths.sort();
+
+        let mut failures = Vec::new();
+
+        for text_path in text_paths {
+            let expected_path = text_path.with_extension("json");
+            let text = fs::read_to_string(&text_path).unwrap();
+
+            let actual = {
+                let mut buf = match parse_group(&text) {
+                    Ok(group) => serde_json::to_string_pretty(&group),
+                    Err(err) => serde_json::to_string_pretty(&serde_json::json!({ "error": err.to_string() })),
+                }.unwrap();
+                buf.push('\n');
+                buf
+            };
+
+            if update {
+                fs::write(&expected_path, &actual).unwrap();
+                continue;
+            }
+
+            let expected = fs::read_to_string(&expected_path).unwrap_or_default();
+
+            if expected != actual {
+                failures.push(format!(
+                    "{}\n{}",
+                    text_path.file_name().unwrap().to_string_lossy(),
+                    line_diff(&expected, &actual)
+                ));
+            }
+        }
+
+        if !failures.is_empty() {
+            panic!(
+                "{} corpus file(s) did not match (- expected, + actual); \
+                set SBB_UPDATE_CORPUS=1 to regenerate:\n\n{}",
+                failures.len(),
+                failures.join("\n")
+            );
+        }
+    }
+
+    /// Produces a line-by-line diff of two strings, prefixing removed lines with `-` and added
+    /// lines with `+`.
+    fn line_diff(expected: &str, actual: &str) -> String {
+        let xs = expected.lines().collect::<Vec<_>>();
+        let ys = actual.lines().collect::<Vec<_>>();
+
+        // Longest common subsequence lengths of each pair of suffixes
+        let mut lcs = vec![vec![0usize; ys.len() + 1]; xs.len() + 1];
+        for i in (0..xs.len()).rev() {
+            for j in (0..ys.len()).rev() {
+                lcs[i][j] = if xs[i] == ys[j] {
+                    lcs[i + 1][j + 1] + 1
+                } else {
+                    lcs[i + 1][j].max(lcs[i][j + 1])
+                };
+            }
+        }
+
+        let mut diff = String::new();
+        let (mut i, mut j) = (0, 0);
+        while i < xs.len() || j < ys.len() {
+            if i < xs.len() && j < ys.len() && xs[i] == ys[j] {
+                diff.push_str("  ");
+                diff.push_str(xs[i]);
+                i += 1;
+                j += 1;
+            } else if i < xs.len() && (j == ys.len() || lcs[i + 1][j] >= lcs[i][j + 1]) {
+                diff.push_str("- ");
+                diff.push_str(xs[i]);
+                i += 1;
+            } else {
+                diff.push_str("+ ");
+                diff.push_str(ys[j]);
+                j += 1;
+            }
+            diff.push('\n');
+        }
+
+        diff
+    }
+
+    /// Generates text which looks roughly like a robot tweet, so that the property tests exercise
+    /// the later stages of the parser rather than failing at the numbers.
+    fn robot_tweet() -> impl proptest::strategy::Strategy<Value = String> {
+        use proptest::prelude::*;
+        use proptest::collection::vec;
+        use proptest::sample::select;
+
+        let number = prop_oneof![
+            0..2000i32,
+            (i32::MAX - 2000)..=i32::MAX,
+            any::<i32>(),
+        ];
+
+        (
+            proptest::option::of("[\\[(]?(CW|TW|cw|content warning)[:]? [a-z ,/&]{0,12}[\\])\n]?"),
+            select(vec!["", "#", "No. "]),
+            vec(number, 1..4),
+            select(vec![", ", "-", "/", " & ", " - "]),
+            select(vec![")", ".", ":", " )", ""]),
+            vec("[A-Za-zÀ-ÿ.]{0,8}([Bb][Oo][Tt]|BOT|b0t)[Ss]?", 0..6),
+            select(vec![" ", ", ", " and ", " & "]),
+            "[ -~\n]{0,30}",
+        )
+            .prop_map(|(cw, number_prefix, numbers, number_sep, numbers_end, names, name_sep, body)| {
+                let numbers = numbers
+                    .iter()
+                    .map(i32::to_string)
+                    .collect::<Vec<_>>()
+                    .join(number_sep);
+
+                format!(
+                    "{}{}{}{} {}. {}",
+                    cw.unwrap_or_default(),
+                    number_prefix,
+                    numbers,
+                    numbers_end,
+                    names.join(name_sep),
+                    body
+                )
+            })
+    }
+
+    proptest::proptest! {
+        #[test]
+        fn prop_parse_group_any_text(text in proptest::arbitrary::any::<String>()) {
+            check_group_invariants(&text);
+        }
+
+        #[test]
+        fn prop_parse_group_robot_tweet(text in robot_tweet()) {
+            check_group_invariants(&text);
+        }
+
+        #[test]
+        fn prop_numbers_range(ns in proptest::collection::vec(proptest::arbitrary::any::<i32>(), 0..6)) {
+            use super::numbers_range;
+
+            let range = numbers_range(&ns);
+
+            match ns.first() {
+                None => proptest::prop_assert!(range.is_none()),
+                Some(&first) => {
+                    let range = range.unwrap();
+                    proptest::prop_assert!(range.start() <= range.end());
+                    proptest::prop_assert!(range.contains(&first));
+
+                    // Numbers which cannot be abbreviations of the first number are always included
+                    for &n in &ns[1..] {
+                        if n <= 0 || n.unsigned_abs() >= first.unsigned_abs() {
+                            proptest::prop_assert!(range.contains(&n));
+                        }
+                    }
+                },
+            }
+        }
+    }
+
+    #[test]
+    fn test_numbers_range_overflow() {
+        use super::numbers_range;
+
+        assert_eq!(numbers_range(&[558, 9]), Some(558..=559));
+        assert_eq!(numbers_range(&[-558, 9]), Some(-559..=-558));
+        assert_eq!(numbers_range(&[i32::MAX, 999_999_999]), Some(999_999_999..=i32::MAX));
+        assert_eq!(numbers_range(&[i32::MIN, 1]), Some(i32::MIN..=-2_147_483_641));
+        assert_eq!(numbers_range(&[i32::MAX - 1, 7]), Some(i32::MAX - 1..=i32::MAX));
     }
 }
@@ -0,0 +1,52 @@
+use std::path::PathBuf;
+
+use anyhow::{anyhow, Context};
+use clap::Parser;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+use crate::parse;
+
+#[derive(Parser, Debug)]
+pub(crate) struct Opts {
+    /// Format the output JSON over multiple lines.
+    #[clap(short, long)]
+    pretty: bool,
+
+    /// The file to read the Tweet text from.
+    /// If omitted, it will be read from stdin instead.
+    file: Option<PathBuf>,
+}
+
+pub(crate) async fn run(opts: Opts) -> anyhow::Result<()> {
+    let text = match opts.file {
+        Some(input_path) =>
+            tokio::fs::read_to_string(&input_path)
+                .await
+                .with_context(|| format!("failed to read input file {}", input_path.to_string_lossy()))?,
+
+        None => {
+            let mut buf = String::new();
+            tokio::io::stdin()
+                .read_to_string(&mut buf)
+                .await
+                .context("failed to read from stdin")?;
+            buf
+        },
+    };
+
+    let group = parse::parse_group(&text)
+        .map_err(|err| anyhow!("{} ({}), near {:?}", err, err.stage(), err.context(&text)))
+        .context("failed to parse tweet text")?;
+
+    let mut group_json = match opts.pretty {
+        true => serde_json::to_string_pretty(&group),
+        false => serde_json::to_string(&group),
+    }.context("failed to serialize parsed robots as json")?;
+
+    group_json.push('\n');
+
+    tokio::io::stdout()
+        .write_all(group_json.as_bytes())
+        .await
+        .context("failed to write to stdout")
+}
@@ -7,6 +7,7 @@ use sqlx::Executor;
 use sqlx::postgres::{PgPool, Postgres};
 
 use crate::model::{self, IdentBuf};
+use crate::parse;
 
 #[derive(Parser, Debug)]
 pub(crate) struct Opts {
@@ -29,6 +30,10 @@ struct DailyOpts {
     /// Delete old scheduled dailies.
     #[clap(short, long)]
     cleanup: bool,
+
+    /// Do not randomly select robots with this content warning. May be given more than once.
+    #[clap(long = "exclude-cw")]
+    exclude_cws: Vec<String>,
 }
 
 pub(crate) async fn run(
@@ -42,6 +47,12 @@ pub(crate) async fn run(
             let now = Utc::now();
             let today = now.date().naive_utc();
 
+            let exclude_cws = daily_opts.exclude_cws
+                .iter()
+                .map(String::as_str)
+                .filter_map(parse::normalise_cw)
+                .collect::<Vec<_>>();
+
             let greetings = lines(include_str!("data/greetings"));
             let intros = lines(include_str!("data/intros"));
 
@@ -56,7 +67,7 @@ pub(crate) async fn run(
                 .context("failed to check for scheduled daily robots")?
             {
                 Some(scheduled) => scheduled,
-                None => random_robot(db_pool, today, daily_opts.no_repeat_days)
+                None => random_robot(db_pool, today, daily_opts.no_repeat_days, &exclude_cws)
                     .await
                     .context("failed to select random daily robot")?,
             };
@@ -80,9 +91,9 @@ pub(crate) async fn run(
             let message = {
                 let mut message = String::new();
     
-                if let Some(ref content_warning) = robot.content_warning {
+                if !robot.content_warnings.is_empty() {
                     message.push_str("[CW: ");
-                    message.push_str(content_warning);
+                    message.push_str(&robot.content_warnings.join(", "));
                     message.push_str("]\n");
                 }
 
@@ -95,8 +106,12 @@ pub(crate) async fn run(
                 message.push_str(&robot.id.number.to_string());
                 message.push_str(", ");
                 message.push_str(&robot.full_name());
-                message.push_str("!\n");
-                message.push_str(&robot.tweet_url());
+                message.push('!');
+
+                if let Some(post_url) = robot.post_url() {
+                    message.push('\n');
+                    message.push_str(&post_url);
+                }
     
                 message
             };
@@ -153,12 +168,14 @@ where
 {
     sqlx::query_as(
         "SELECT \
-            id, prefix, suffix, plural, tweet_id, content_warning \
-        FROM robots \
+            r.id, r.prefix, r.suffix, r.plural, g.platform, g.source_ref, \
+            g.deleted_upstream_at IS NOT NULL AS tweet_deleted, g.content_warnings \
+        FROM robots r \
+        JOIN robot_groups g ON g.id = r.group_id \
         WHERE EXISTS (\
             SELECT 1 FROM scheduled_dailies \
             WHERE \
-                robots.id = scheduled_dailies.robot_id \
+                r.id = scheduled_dailies.robot_id \
                 AND scheduled_dailies.post_on = $1) \
         LIMIT 1",
     )
@@ -170,26 +187,43 @@ where
 async fn random_robot<'e, E>(
     db_exec: E,
     today: NaiveDate,
-    no_repeat_days: i64
+    no_repeat_days: i64,
+    exclude_cws: &[String]
 ) -> sqlx::Result<model::DailyRobot>
 where
     E: Executor<'e, Database = Postgres>
 {
     let reuse_cutoff_date = today - Duration::days(no_repeat_days);
 
+    // Select a group first and then one of its robots, so that the robots of a group are
+    // collectively as likely to be selected as a robot published on its own. A group cannot be
+    // selected again until none of its robots have been posted for `no_repeat_days`.
     sqlx::query_as(
         "SELECT \
-            id, prefix, suffix, plural, tweet_id, content_warning \
-        FROM robots \
-        WHERE NOT EXISTS (\
-            SELECT 1 FROM past_dailies \
-            WHERE \
-                past_dailies.robot_id = robots.id \
-                AND past_dailies.posted_on >= $1) \
+            r.id, r.prefix, r.suffix, r.plural, g.platform, g.source_ref, \
+            g.deleted_upstream_at IS NOT NULL AS tweet_deleted, g.content_warnings \
+        FROM robots r \
+        JOIN robot_groups g ON g.id = r.group_id \
+        WHERE NOT r.needs_review \
+            AND g.id = (\
+                SELECT g.id FROM robot_groups g \
+                WHERE NOT g.content_warnings && $2 \
+                    AND EXISTS (\
+                        SELECT 1 FROM robots r \
+                        WHERE r.group_id = g.id AND NOT r.needs_review) \
+                    AND NOT EXISTS (\
+                        SELECT 1 FROM past_dailies \
+                        JOIN robots r ON r.id = past_dailies.robot_id \
+                        WHERE \
+                            r.group_id = g.id \
+                            AND past_dailies.posted_on >= $1) \
+                ORDER BY random() \
+                LIMIT 1) \
         ORDER BY random() \
         LIMIT 1"
     )
     .bind(reuse_cutoff_date)
+    .bind(exclude_cws)
     .fetch_one(db_exec)
     .await
 }
@@ -0,0 +1,216 @@
+use std::collections::HashSet;
+use std::path::PathBuf;
+
+use anyhow::{anyhow, Context};
+use clap::Parser;
+use goldcrest::TweetOptions;
+use sqlx::postgres::PgPool;
+
+use crate::GoldcrestConfig;
+use crate::fetch::TWEETS_PER_REQUEST;
+use crate::model::{self, QuarantinedTweet};
+use crate::overrides::ParseOverrides;
+use crate::scribe::{self, ScribeOptions};
+
+#[derive(Parser, Debug)]
+pub(crate) struct Opts {
+    #[clap(subcommand)]
+    subcommand: Subcommand,
+}
+
+#[derive(Parser, Debug)]
+enum Subcommand {
+    /// List the quarantined Tweets and why they could not be stored.
+    List(ListOpts),
+
+    /// Show the full details of a quarantined Tweet.
+    Show(ShowOpts),
+
+    /// Fetch quarantined Tweets again and try to store them.
+    Retry(RetryOpts),
+
+    /// Mark quarantined Tweets as not being robots, so that they are no longer listed or retried.
+    Dismiss(DismissOpts),
+}
+
+#[derive(Parser, Debug)]
+struct ListOpts {
+    /// Include dismissed Tweets.
+    #[clap(short, long)]
+    all: bool,
+}
+
+#[derive(Parser, Debug)]
+struct ShowOpts {
+    tweet_id: u64,
+}
+
+#[derive(Parser, Debug)]
+struct RetryOpts {
+    /// Retry every quarantined Tweet which has not been dismissed.
+    #[clap(short, long)]
+    all: bool,
+
+    /// Skip robot Tweets which parsed with warnings, rather than storing them flagged for review.
+    #[clap(long)]
+    strict: bool,
+
+    /// Update robots which have already been stored, rather than skipping their Tweets.
+    #[clap(long)]
+    update: bool,
+
+    /// A json file of manual corrections for robot Tweets which cannot be parsed correctly.
+    #[clap(long)]
+    overrides: Option<PathBuf>,
+
+    tweet_ids: Vec<u64>,
+}
+
+#[derive(Parser, Debug)]
+struct DismissOpts {
+    #[clap(required = true)]
+    tweet_ids: Vec<u64>,
+}
+
+pub(crate) async fn run(
+    db_pool: &PgPool,
+    goldcrest_config: GoldcrestConfig,
+    opts: Opts
+) -> anyhow::Result<()>
+{
+    match opts.subcommand {
+        Subcommand::List(opts) => list(db_pool, opts).await,
+        Subcommand::Show(opts) => show(db_pool, opts).await,
+        Subcommand::Retry(opts) => {
+            // Only connect to goldcrest when it is needed, so that the quarantine can be reviewed
+            // without Twitter credentials
+            let au_client = crate::connect_goldcrest(goldcrest_config).await?;
+            retry(db_pool, &au_client, opts).await
+        },
+        Subcommand::Dismiss(opts) => dismiss(db_pool, opts).await,
+    }
+}
+
+async fn list(db_pool: &PgPool, opts: ListOpts) -> anyhow::Result<()> {
+    let tweets = sqlx::query_as::<_, QuarantinedTweet>(
+        "SELECT * FROM quarantine WHERE $1 OR NOT dismissed ORDER BY first_seen_at, tweet_id"
+    )
+    .bind(opts.all)
+    .fetch_all(db_pool)
+    .await
+    .context("failed to get quarantined tweets")?;
+
+    for tweet in tweets {
+        print!("{}  {}  {}", tweet.tweet_id, tweet.first_seen_at.format("%Y-%m-%d"), tweet.reason);
+        if tweet.dismissed {
+            print!(" (dismissed)");
+        }
+        println!();
+    }
+
+    Ok(())
+}
+
+async fn show(db_pool: &PgPool, opts: ShowOpts) -> anyhow::Result<()> {
+    let tweet = sqlx::query_as::<_, QuarantinedTweet>("SELECT * FROM quarantine WHERE tweet_id = $1")
+        .bind(opts.tweet_id as i64)
+        .fetch_optional(db_pool)
+        .await
+        .context("failed to get quarantined tweet")?
+        .ok_or_else(|| anyhow!("tweet {} is not quarantined", opts.tweet_id))?;
+
+    println!("tweet:       https://twitter.com/smolrobots/status/{}", tweet.tweet_id);
+    println!("tweeted:     {}", tweet.tweet_time);
+    println!("first seen:  {}", tweet.first_seen_at);
+    println!("last seen:   {}", tweet.last_seen_at);
+    println!("dismissed:   {}", if tweet.dismissed { "yes" } else { "no" });
+    println!("reason:      {}", tweet.reason);
+    println!();
+    println!("{}", tweet.text);
+
+    Ok(())
+}
+
+async fn retry(db_pool: &PgPool, au_client: &goldcrest::Client, opts: RetryOpts) -> anyhow::Result<()> {
+    let mut tweet_ids = opts.tweet_ids;
+
+    if opts.all {
+        tweet_ids.extend(
+            sqlx::query_as::<_, model::TweetId>("SELECT tweet_id FROM quarantine WHERE NOT dismissed")
+                .fetch_all(db_pool)
+                .await
+                .context("failed to get quarantined tweets")?
+                .into_iter()
+                .map(|row| row.tweet_id as u64));
+    }
+
+    if tweet_ids.is_empty() {
+        return Err(anyhow!("no tweets to retry; give some tweet ids or use --all"));
+    }
+
+    tweet_ids.sort_unstable();
+    tweet_ids.dedup();
+
+    let overrides = ParseOverrides::load(opts.overrides.as_deref()).await?;
+
+    let scribe_opts = ScribeOptions {
+        verbose: true,
+        strict: opts.strict,
+        update: opts.update,
+    };
+
+    let mut db_conn = db_pool.acquire()
+        .await
+        .context("failed to connect to database")?;
+
+    let mut scribed = Vec::new();
+
+    for ids in tweet_ids.chunks(TWEETS_PER_REQUEST) {
+        let tweets = au_client
+            .get_tweets(ids.to_vec(), TweetOptions::default())
+            .await
+            .context("failed to fetch tweets")?;
+
+        let received = tweets
+            .iter()
+            .map(|tweet| scribe::tweet_original(tweet).id)
+            .collect::<HashSet<_>>();
+
+        for id in ids.iter().filter(|id| !received.contains(id)) {
+            eprintln!("skip tweet {}: tweet could not be retrieved", id);
+        }
+
+        scribed.extend(
+            scribe::scribe_tweets(&mut db_conn, &tweets, &overrides, scribe_opts)
+                .await
+                .context("failed to store tweets")?);
+    }
+
+    scribe::report_stored(&scribe::stored_robots(&scribed), opts.update);
+
+    Ok(())
+}
+
+async fn dismiss(db_pool: &PgPool, opts: DismissOpts) -> anyhow::Result<()> {
+    let tweet_ids = opts.tweet_ids
+        .iter()
+        .map(|&id| id as i64)
+        .collect::<Vec<_>>();
+
+    let dismissed = sqlx::query_as::<_, model::TweetId>(
+        "UPDATE quarantine SET dismissed = TRUE WHERE tweet_id = ANY($1) RETURNING tweet_id"
+    )
+    .bind(&tweet_ids)
+    .fetch_all(db_pool)
+    .await
+    .context("failed to dismiss quarantined tweets")?
+    .into_iter()
+    .map(|row| row.tweet_id)
+    .collect::<HashSet<_>>();
+
+    for id in tweet_ids.iter().filter(|id| !dismissed.contains(id)) {
+        eprintln!("tweet {} is not quarantined", id);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,425 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::path::PathBuf;
+
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use clap::Parser;
+use sqlx::FromRow;
+use sqlx::postgres::{PgConnection, PgPool};
+
+use crate::model::{IdentBuf, Platform, RawTweet, RobotParsedFields};
+use crate::overrides::ParseOverrides;
+use crate::parse;
+use crate::scribe::{self, GroupValues, MediaEntry, RobotValues};
+
+#[derive(Parser, Debug)]
+pub(crate) struct Opts {
+    /// Apply the changes to the database, rather than only printing them.
+    #[clap(short, long)]
+    apply: bool,
+
+    /// Only reparse the Tweet with the given id. May be given more than once.
+    /// If omitted, every stored Tweet is reparsed, including quarantined Tweets.
+    #[clap(short, long = "tweet")]
+    tweets: Vec<i64>,
+
+    /// A json file of manual corrections for robot Tweets which cannot be parsed correctly.
+    #[clap(long)]
+    overrides: Option<PathBuf>,
+}
+
+/// The changes to the robots of a single tweet which result from reparsing it.
+struct TweetDiff<'a> {
+    raw_tweet: &'a RawTweet,
+    added: Vec<RobotParsedFields>,
+    changed: Vec<(&'a RobotParsedFields, RobotParsedFields)>,
+    removed: Vec<&'a RobotParsedFields>,
+}
+
+impl<'a> TweetDiff<'a> {
+    fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.changed.is_empty() && self.removed.is_empty()
+    }
+}
+
+impl<'a> fmt::Display for TweetDiff<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "tweet {}", self.raw_tweet.tweet_id)?;
+
+        for robot in &self.added {
+            writeln!(f, "  + {}", robot.id)?;
+            write_field(f, "prefix", &robot.prefix)?;
+            write_field(f, "suffix", &robot.suffix)?;
+            write_field(f, "plural", &robot.plural)?;
+            write_field(f, "body", &robot.body)?;
+            write_field(f, "content_warnings", &robot.content_warnings)?;
+            write_field(f, "needs_review", &robot.needs_review)?;
+        }
+
+        for (old, new) in &self.changed {
+            writeln!(f, "  ~ {}", new.id)?;
+            write_field_change(f, "prefix", &old.prefix, &new.prefix)?;
+            write_field_change(f, "suffix", &old.suffix, &new.suffix)?;
+            write_field_change(f, "plural", &old.plural, &new.plural)?;
+            write_field_change(f, "body", &old.body, &new.body)?;
+            write_field_change(f, "content_warnings", &old.content_warnings, &new.content_warnings)?;
+            write_field_change(f, "needs_review", &old.needs_review, &new.needs_review)?;
+        }
+
+        for robot in &self.removed {
+            writeln!(f, "  - {} (no longer parsed, will be flagged for review)", robot.id)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// The values of a stored robot group which are kept when it is reparsed.
+#[derive(FromRow, Debug)]
+struct ExistingGroup {
+    id: i32,
+    tweet_time: DateTime<Utc>,
+    image_url: String,
+    alt: Option<String>,
+}
+
+fn write_field<T: fmt::Debug>(f: &mut fmt::Formatter, name: &str, val: &T) -> fmt::Result {
+    writeln!(f, "      {}: {:?}", name, val)
+}
+
+fn write_field_change<T>(f: &mut fmt::Formatter, name: &str, old: &T, new: &T) -> fmt::Result
+where
+    T: fmt::Debug + PartialEq,
+{
+    if old != new {
+        writeln!(f, "      {}: {:?} -> {:?}", name, old, new)?;
+    }
+    Ok(())
+}
+
+pub(crate) async fn run(db_pool: &PgPool, opts: Opts) -> anyhow::Result<()> {
+    let overrides = ParseOverrides::load(opts.overrides.as_deref()).await?;
+
+    let raw_tweets: Vec<RawTweet> = sqlx::query_as(
+        "SELECT tweet_id, tweet_time, full_text, entities, media FROM raw_tweets \
+        WHERE cardinality($1::INT8[]) = 0 OR tweet_id = ANY($1) \
+        ORDER BY tweet_id"
+    )
+    .bind(&opts.tweets)
+    .fetch_all(db_pool)
+    .await
+    .context("failed to get raw tweets from database")?;
+
+    let tweet_ids = raw_tweets
+        .iter()
+        .map(|raw_tweet| raw_tweet.tweet_id)
+        .collect::<Vec<_>>();
+
+    let stored_robots: Vec<RobotParsedFields> = sqlx::query_as(
+        "SELECT r.id, g.tweet_id, r.prefix, r.suffix, r.plural, g.body, g.content_warnings, r.needs_review \
+        FROM robots r \
+        JOIN robot_groups g ON g.id = r.group_id \
+        WHERE g.tweet_id = ANY($1)"
+    )
+    .bind(&tweet_ids)
+    .fetch_all(db_pool)
+    .await
+    .context("failed to get robots from database")?;
+
+    let mut stored_robots_by_tweet = HashMap::<i64, Vec<&RobotParsedFields>>::new();
+    for robot in &stored_robots {
+        stored_robots_by_tweet.entry(robot.tweet_id).or_default().push(robot);
+    }
+
+    let mut diffs = Vec::new();
+
+    for raw_tweet in &raw_tweets {
+        let stored = stored_robots_by_tweet
+            .get(&raw_tweet.tweet_id)
+            .map(Vec::as_slice)
+            .unwrap_or_default();
+
+        match diff_tweet(raw_tweet, stored, &overrides) {
+            Ok(diff) => if !diff.is_empty() {
+                diffs.push(diff);
+            },
+            Err(err) => eprintln!("skip tweet {}: {}", raw_tweet.tweet_id, err),
+        }
+    }
+
+    // Robots added by the reparse must not clash with the robots of other tweets, so skip any
+    // tweets which would add a robot that already exists elsewhere.
+    let added_ids = diffs
+        .iter()
+        .flat_map(|diff| diff.added.iter().map(|robot| robot.id.clone()))
+        .collect::<Vec<_>>();
+
+    let existing_ids: Vec<IdentBuf> = sqlx::query_scalar(
+        "SELECT id FROM robots WHERE id = ANY($1)"
+    )
+    .bind(&added_ids)
+    .fetch_all(db_pool)
+    .await
+    .context("failed to get existing robot ids from database")?;
+
+    let mut claimed_ids = existing_ids
+        .into_iter()
+        .collect::<HashSet<_>>();
+
+    diffs.retain(|diff| {
+        match diff.added.iter().find(|robot| claimed_ids.contains(&robot.id)) {
+            Some(robot) => {
+                eprintln!("skip tweet {}: robot {} already exists", diff.raw_tweet.tweet_id, robot.id);
+                false
+            },
+            None => {
+                claimed_ids.extend(diff.added.iter().map(|robot| robot.id.clone()));
+                true
+            },
+        }
+    });
+
+    for diff in &diffs {
+        print!("{}", diff);
+    }
+
+    println!("{} tweets reparsed, {} with changes", raw_tweets.len(), diffs.len());
+
+    if diffs.is_empty() {
+        return Ok(());
+    }
+
+    if !opts.apply {
+        println!("run again with --apply to store these changes");
+        return Ok(());
+    }
+
+    let mut tx = db_pool.begin()
+        .await
+        .context("failed to begin transaction")?;
+
+    for diff in &diffs {
+        apply_diff(&mut tx, diff)
+            .await
+            .with_context(|| format!("failed to apply changes for tweet {}", diff.raw_tweet.tweet_id))?;
+    }
+
+    tx.commit()
+        .await
+        .context("failed to commit changes")?;
+
+    println!("changes applied");
+
+    Ok(())
+}
+
+fn diff_tweet<'a>(
+    raw_tweet: &'a RawTweet,
+    stored: &[&'a RobotParsedFields],
+    overrides: &ParseOverrides,
+) -> anyhow::Result<TweetDiff<'a>>
+{
+    let text = parser_text(raw_tweet);
+
+    let group = match overrides.get(raw_tweet.tweet_id as u64) {
+        Some(parse_override) => parse_override.group(),
+
+        None => parse::parse_group(&text)
+            .map_err(|err| anyhow::anyhow!(
+                "could not parse robot data ({}): {}, near {:?}",
+                err.stage(), err, err.context(&text)
+            ))?,
+    };
+
+    let body = group.body.trim();
+    let needs_review = !group.warnings.is_empty();
+
+    let mut diff = TweetDiff {
+        raw_tweet,
+        added: Vec::new(),
+        changed: Vec::new(),
+        removed: Vec::new(),
+    };
+
+    let mut seen_ids = HashSet::new();
+
+    for robot in &group.robots {
+        let reparsed = RobotParsedFields {
+            id: robot.ident(),
+            tweet_id: raw_tweet.tweet_id,
+            prefix: robot.name.prefix.clone().into_owned(),
+            suffix: robot.name.suffix.clone().into_owned(),
+            plural: robot.name.plural.clone().map(|plural| plural.into_owned()),
+            body: body.to_owned(),
+            content_warnings: group.content_warnings.clone(),
+            needs_review,
+        };
+
+        if !seen_ids.insert(reparsed.id.clone()) {
+            anyhow::bail!("robot {} appears more than once", reparsed.id);
+        }
+
+        match stored.iter().find(|stored_robot| stored_robot.id == reparsed.id) {
+            Some(&stored_robot) => if *stored_robot != reparsed {
+                diff.changed.push((stored_robot, reparsed));
+            },
+            None => diff.added.push(reparsed),
+        }
+    }
+
+    diff.removed.extend(stored
+        .iter()
+        .copied()
+        .filter(|stored_robot| !seen_ids.contains(&stored_robot.id) && !stored_robot.needs_review));
+
+    if !diff.added.is_empty() && robot_media(raw_tweet).is_empty() {
+        anyhow::bail!("tweet does not contain media");
+    }
+
+    Ok(diff)
+}
+
+/// Leaves the media links and urls out of the full text of the tweet, as scribe does before parsing
+/// it.
+fn parser_text(raw_tweet: &RawTweet) -> String {
+    let removed_ranges = raw_tweet.media
+        .iter()
+        .filter_map(|media| media.indices)
+        .chain(raw_tweet.entities.urls.iter().filter_map(|url| url.indices))
+        .collect::<Vec<_>>();
+
+    scribe::remove_char_ranges(&raw_tweet.full_text, &removed_ranges)
+        .trim()
+        .to_owned()
+}
+
+fn robot_media(raw_tweet: &RawTweet) -> Vec<MediaEntry<'_>> {
+    raw_tweet.media
+        .iter()
+        .filter(|media| scribe::is_valid_robot_media_type(&media.media_type))
+        .map(MediaEntry::from_raw_media)
+        .collect()
+}
+
+async fn apply_diff(db_conn: &mut PgConnection, diff: &TweetDiff<'_>) -> anyhow::Result<()> {
+    let tweet_id = diff.raw_tweet.tweet_id;
+
+    // The body and content warnings are shared by all of the robots of the tweet, so every reparsed
+    // robot has the same values for them
+    let reparsed = diff.changed
+        .iter()
+        .map(|(_, robot)| robot)
+        .chain(diff.added.iter())
+        .next();
+
+    let group: Option<ExistingGroup> = sqlx::query_as(
+        "SELECT id, tweet_time, image_url, alt FROM robot_groups WHERE tweet_id = $1"
+    )
+    .bind(tweet_id)
+    .fetch_optional(&mut *db_conn)
+    .await
+    .context("failed to get robot group")?;
+
+    let group_id = match (group, reparsed) {
+        (Some(group), Some(reparsed)) => {
+            scribe::update_group(&mut *db_conn, group.id, &GroupValues {
+                tweet_time: group.tweet_time,
+                image_url: &group.image_url,
+                body: &reparsed.body,
+                alt: group.alt.as_deref(),
+                content_warnings: &reparsed.content_warnings,
+            })
+            .await
+            .context("failed to update robot group")?;
+
+            Some(group.id)
+        },
+
+        (Some(group), None) => Some(group.id),
+
+        // The tweet has no group, either because it was quarantined or because its robots were
+        // removed with `sbb robot remove`, so a new one is needed for the added robots
+        (None, Some(reparsed)) => {
+            let media = robot_media(diff.raw_tweet);
+
+            let primary_media = media
+                .first()
+                .context("tweet does not contain media")?;
+
+            let group_id: i32 = sqlx::query_scalar(
+                "INSERT INTO robot_groups \
+                    (platform, source_ref, tweet_time, image_url, body, alt, content_warnings) \
+                VALUES \
+                    ($1, $2, $3, $4, $5, $6, $7) \
+                RETURNING id"
+            )
+            .bind(Platform::Twitter)
+            .bind(tweet_id.to_string())
+            .bind(diff.raw_tweet.tweet_time)
+            .bind(primary_media.url)
+            .bind(&reparsed.body)
+            .bind(primary_media.alt)
+            .bind(&reparsed.content_warnings)
+            .fetch_one(&mut *db_conn)
+            .await
+            .context("failed to insert robot group")?;
+
+            scribe::store_group_media(&mut *db_conn, group_id, &media)
+                .await
+                .context("failed to insert media for robot group")?;
+
+            Some(group_id)
+        },
+
+        (None, None) => None,
+    };
+
+    for (_, robot) in &diff.changed {
+        scribe::update_robot(&mut *db_conn, &robot.id, &RobotValues {
+            prefix: &robot.prefix,
+            suffix: &robot.suffix,
+            plural: robot.plural.as_deref(),
+            needs_review: robot.needs_review,
+        })
+        .await
+        .with_context(|| format!("failed to update robot {}", robot.id))?;
+    }
+
+    if let Some(group_id) = group_id {
+        for robot in &diff.added {
+            sqlx::query(
+                "INSERT INTO robots \
+                    (id, group_id, prefix, suffix, plural, needs_review) \
+                VALUES \
+                    ($1, $2, $3, $4, $5, $6)"
+            )
+            .bind(&robot.id)
+            .bind(group_id)
+            .bind(&robot.prefix)
+            .bind(&robot.suffix)
+            .bind(&robot.plural)
+            .bind(robot.needs_review)
+            .execute(&mut *db_conn)
+            .await
+            .with_context(|| format!("failed to insert robot {}", robot.id))?;
+        }
+    }
+
+    // A quarantined tweet no longer needs reviewing once its robots have been stored
+    if !diff.added.is_empty() {
+        sqlx::query("DELETE FROM quarantine WHERE tweet_id = $1")
+            .bind(tweet_id)
+            .execute(&mut *db_conn)
+            .await
+            .context("failed to remove tweet from quarantine")?;
+    }
+
+    for robot in &diff.removed {
+        scribe::flag_robot_for_review(&mut *db_conn, &robot.id)
+            .await
+            .with_context(|| format!("failed to flag robot {} for review", robot.id))?;
+    }
+
+    Ok(())
+}
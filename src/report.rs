@@ -0,0 +1,200 @@
+use std::path::Path;
+use std::time::Instant;
+
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+use crate::scribe::{InvalidTweet, ScribedPost, StoreOutcome};
+
+/// A json report of what happened to each tweet during a `fetch` or `timeline` run, written with the
+/// `--report` option so that ingestion can be monitored without changing what is printed to stdout.
+#[derive(Serialize, Debug)]
+pub(crate) struct IngestReport {
+    command: &'static str,
+    started_at: String,
+    finished_at: String,
+    duration_secs: f64,
+    totals: Totals,
+    tweets: Vec<TweetReport>,
+}
+
+#[derive(Serialize, Default, Debug)]
+struct Totals {
+    tweets: usize,
+    scribed: usize,
+    duplicate: usize,
+    parse_failure: usize,
+    missing_media: usize,
+    not_returned: usize,
+    unavailable: usize,
+    failed: usize,
+    robots_inserted: usize,
+    robots_updated: usize,
+    robots_unchanged: usize,
+    robots_flagged: usize,
+}
+
+#[derive(Serialize, Debug)]
+struct TweetReport {
+    tweet_id: u64,
+    #[serde(flatten)]
+    outcome: TweetOutcome,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(tag = "outcome", rename_all = "snake_case")]
+enum TweetOutcome {
+    Scribed { robots: Vec<RobotReport> },
+    Duplicate { reason: String },
+    ParseFailure { reason: String },
+    MissingMedia,
+    NotReturned,
+    Unavailable,
+    Failed { reason: String },
+}
+
+#[derive(Serialize, Debug)]
+struct RobotReport {
+    id: String,
+    outcome: StoreOutcome,
+}
+
+/// Collects the outcome of each tweet of an ingest run, along with when the run started.
+pub(crate) struct IngestRecorder {
+    command: &'static str,
+    started_at: DateTime<Utc>,
+    started: Instant,
+    tweets: Vec<TweetReport>,
+}
+
+impl IngestRecorder {
+    pub(crate) fn start(command: &'static str) -> Self {
+        Self {
+            command,
+            started_at: Utc::now(),
+            started: Instant::now(),
+            tweets: Vec::new(),
+        }
+    }
+
+    /// Records the outcomes of scribed tweets. Posts from other platforms are left out, since the
+    /// report is only written by the commands which read tweets.
+    pub(crate) fn scribed(&mut self, tweets: &[ScribedPost]) {
+        let tweets = tweets
+            .iter()
+            .filter_map(|tweet| Some((tweet.source.tweet_id()?, tweet)));
+
+        self.tweets.extend(tweets.map(|(tweet_id, tweet)| TweetReport {
+            tweet_id,
+            outcome: match &tweet.result {
+                Ok(robots) => TweetOutcome::Scribed {
+                    robots: robots
+                        .iter()
+                        .map(|robot| RobotReport {
+                            id: robot.id.to_string(),
+                            outcome: robot.outcome,
+                        })
+                        .collect(),
+                },
+
+                Err(err @ (InvalidTweet::AlreadyStored | InvalidTweet::DuplicateRobot(_))) =>
+                    TweetOutcome::Duplicate { reason: err.to_string() },
+
+                Err(InvalidTweet::MissingMedia) => TweetOutcome::MissingMedia,
+
+                Err(err @ (InvalidTweet::ParseUnsuccessful { .. }
+                    | InvalidTweet::ParseWarnings(_)
+                    | InvalidTweet::NoRobots)) =>
+                    TweetOutcome::ParseFailure { reason: err.to_string() },
+            },
+        }));
+    }
+
+    /// Records tweets which were skipped without being requested because they have already been
+    /// stored.
+    pub(crate) fn already_stored(&mut self, tweet_ids: impl IntoIterator<Item = u64>) {
+        self.tweets.extend(tweet_ids.into_iter().map(|tweet_id| TweetReport {
+            tweet_id,
+            outcome: TweetOutcome::Duplicate { reason: InvalidTweet::AlreadyStored.to_string() },
+        }));
+    }
+
+    /// Records tweets which were requested but not returned by the Twitter API, e.g. because they
+    /// have been deleted.
+    pub(crate) fn not_returned(&mut self, tweet_ids: impl IntoIterator<Item = u64>) {
+        self.tweets.extend(tweet_ids.into_iter().map(|tweet_id| TweetReport {
+            tweet_id,
+            outcome: TweetOutcome::NotReturned,
+        }));
+    }
+
+    /// Records tweets which were skipped without being requested because an earlier fetch found
+    /// them to be unavailable.
+    pub(crate) fn unavailable(&mut self, tweet_ids: impl IntoIterator<Item = u64>) {
+        self.tweets.extend(tweet_ids.into_iter().map(|tweet_id| TweetReport {
+            tweet_id,
+            outcome: TweetOutcome::Unavailable,
+        }));
+    }
+
+    /// Records tweets whose batch could not be fetched or stored, so their outcome is unknown.
+    pub(crate) fn failed(&mut self, tweet_ids: impl IntoIterator<Item = u64>, reason: &str) {
+        self.tweets.extend(tweet_ids.into_iter().map(|tweet_id| TweetReport {
+            tweet_id,
+            outcome: TweetOutcome::Failed { reason: reason.to_owned() },
+        }));
+    }
+
+    pub(crate) fn finish(mut self) -> IngestReport {
+        self.tweets.sort_by_key(|tweet| tweet.tweet_id);
+
+        let mut totals = Totals {
+            tweets: self.tweets.len(),
+            ..Totals::default()
+        };
+
+        for tweet in &self.tweets {
+            match &tweet.outcome {
+                TweetOutcome::Scribed { .. } => totals.scribed += 1,
+                TweetOutcome::Duplicate { .. } => totals.duplicate += 1,
+                TweetOutcome::ParseFailure { .. } => totals.parse_failure += 1,
+                TweetOutcome::MissingMedia => totals.missing_media += 1,
+                TweetOutcome::NotReturned => totals.not_returned += 1,
+                TweetOutcome::Unavailable => totals.unavailable += 1,
+                TweetOutcome::Failed { .. } => totals.failed += 1,
+            }
+
+            if let TweetOutcome::Scribed { robots } = &tweet.outcome {
+                for robot in robots {
+                    match robot.outcome {
+                        StoreOutcome::Inserted => totals.robots_inserted += 1,
+                        StoreOutcome::Updated => totals.robots_updated += 1,
+                        StoreOutcome::Unchanged => totals.robots_unchanged += 1,
+                        StoreOutcome::Flagged => totals.robots_flagged += 1,
+                    }
+                }
+            }
+        }
+
+        IngestReport {
+            command: self.command,
+            started_at: self.started_at.to_rfc3339(),
+            finished_at: Utc::now().to_rfc3339(),
+            duration_secs: self.started.elapsed().as_secs_f64(),
+            totals,
+            tweets: self.tweets,
+        }
+    }
+}
+
+impl IngestReport {
+    pub(crate) async fn write(&self, path: &Path) -> anyhow::Result<()> {
+        let report_json = serde_json::to_string_pretty(self)
+            .context("failed to serialize ingest report as json")?;
+
+        tokio::fs::write(path, report_json)
+            .await
+            .with_context(|| format!("failed to write ingest report to {}", path.to_string_lossy()))
+    }
+}
@@ -0,0 +1,604 @@
+use std::borrow::Cow;
+use std::env;
+
+use anyhow::{anyhow, Context};
+use chrono::{DateTime, Utc};
+use clap::Parser;
+use sqlx::Connection;
+use sqlx::postgres::{PgConnection, PgPool};
+use sqlx::types::Json;
+
+use crate::ident;
+use crate::model::{IdentBuf, Platform, RobotGroup, RobotRecord};
+use crate::parse::{self, Robot, RobotName};
+use crate::scribe::{self, MediaEntry};
+
+#[derive(Parser, Debug)]
+pub(crate) struct Opts {
+    /// The name recorded as having made the change. Defaults to the value of the USER
+    /// environment variable.
+    #[clap(long, global = true)]
+    by: Option<String>,
+
+    #[clap(subcommand)]
+    subcommand: Subcommand,
+}
+
+#[derive(Parser, Debug)]
+enum Subcommand {
+    /// Add a robot which does not come from a robot Tweet.
+    Add(AddOpts),
+
+    /// Change the name, description or content warnings of a robot. The description and content
+    /// warnings are shared by every robot in the robot's group.
+    Edit(EditOpts),
+
+    /// Remove a robot, and its group if no other robots are left in it.
+    Remove(RemoveOpts),
+
+    /// Add an alternative id which can be used to refer to a robot, e.g. "123/teabot" for 123/tea.
+    Alias(AliasOpts),
+
+    /// Remove an alternative id added with the alias subcommand.
+    Unalias(UnaliasOpts),
+}
+
+#[derive(Parser, Debug)]
+struct AddOpts {
+    number: i32,
+
+    /// The portion of the robot's name before "bot", e.g. "Tea" for Teabot.
+    #[clap(long)]
+    prefix: String,
+
+    #[clap(long, default_value = "bot")]
+    suffix: String,
+
+    /// The ending added to the suffix if the robot's name is plural, e.g. "s" for Mischiefbots.
+    #[clap(long)]
+    plural: Option<String>,
+
+    #[clap(long)]
+    body: String,
+
+    #[clap(long)]
+    image_url: String,
+
+    #[clap(long)]
+    alt: Option<String>,
+
+    /// A content warning for the robot. May be given more than once.
+    #[clap(long = "cw")]
+    content_warnings: Vec<String>,
+
+    /// The Tweet the robot appeared in, if any.
+    #[clap(long)]
+    tweet: Option<u64>,
+
+    /// When the robot was first published, as an RFC 3339 timestamp. Defaults to now.
+    #[clap(long)]
+    time: Option<DateTime<Utc>>,
+}
+
+#[derive(Parser, Debug)]
+struct EditOpts {
+    /// The id of the robot to edit, e.g. "123/tea". May also be one of the robot's aliases, or its
+    /// number alone if no other robot has the same number.
+    id: String,
+
+    #[clap(long)]
+    number: Option<i32>,
+
+    #[clap(long)]
+    prefix: Option<String>,
+
+    #[clap(long)]
+    suffix: Option<String>,
+
+    #[clap(long)]
+    plural: Option<String>,
+
+    /// Make the robot's name singular.
+    #[clap(long, conflicts_with = "plural")]
+    no_plural: bool,
+
+    #[clap(long)]
+    body: Option<String>,
+
+    /// Replace the robot's content warnings. May be given more than once.
+    #[clap(long = "cw")]
+    content_warnings: Vec<String>,
+
+    /// Remove all of the robot's content warnings.
+    #[clap(long, conflicts_with = "content-warnings")]
+    no_cws: bool,
+
+    /// Mark the robot as reviewed, so that it can be selected as the daily robot again.
+    #[clap(long)]
+    reviewed: bool,
+
+    /// Flag the robot for review.
+    #[clap(long, conflicts_with = "reviewed")]
+    needs_review: bool,
+}
+
+#[derive(Parser, Debug)]
+struct RemoveOpts {
+    /// The id, alias or number of the robot to remove.
+    id: String,
+
+    /// Remove the robot even if it has been posted or scheduled as the daily robot. Its past and
+    /// scheduled dailies are removed along with it.
+    #[clap(long)]
+    force: bool,
+}
+
+#[derive(Parser, Debug)]
+struct AliasOpts {
+    /// The id, alias or number of the robot to add the alias to.
+    robot: String,
+
+    /// The alias to add, in the same "number/name" form as robot ids.
+    alias: String,
+}
+
+#[derive(Parser, Debug)]
+struct UnaliasOpts {
+    alias: String,
+}
+
+pub(crate) async fn run(db_pool: &PgPool, opts: Opts) -> anyhow::Result<()> {
+    let editor = match opts.by.or_else(|| env::var("USER").ok()) {
+        Some(editor) if !editor.trim().is_empty() => editor.trim().to_owned(),
+        _ => return Err(anyhow!("could not determine who is making the change; use --by")),
+    };
+
+    let mut db_conn = db_pool.acquire()
+        .await
+        .context("failed to connect to database")?;
+
+    match opts.subcommand {
+        Subcommand::Add(opts) => add(&mut db_conn, &editor, opts).await,
+        Subcommand::Edit(opts) => edit(&mut db_conn, &editor, opts).await,
+        Subcommand::Remove(opts) => remove(&mut db_conn, &editor, opts).await,
+        Subcommand::Alias(opts) => alias(&mut db_conn, &editor, opts).await,
+        Subcommand::Unalias(opts) => unalias(&mut db_conn, &editor, opts).await,
+    }
+}
+
+async fn add(db_conn: &mut PgConnection, editor: &str, opts: AddOpts) -> anyhow::Result<()> {
+    let name = robot_name(&opts.prefix, &opts.suffix, opts.plural.as_deref());
+    let id = robot_ident(opts.number, &name)?;
+    let content_warnings = normalise_cws(&opts.content_warnings);
+    let body = opts.body.trim();
+    let alt = opts.alt.as_deref().map(str::trim).filter(|alt| !alt.is_empty());
+    let tweet_id = opts.tweet.map(|tweet_id| tweet_id as i64);
+
+    let mut tx = db_conn.begin().await?;
+
+    if let Some(tweet_id) = tweet_id {
+        let exists: bool = sqlx::query_scalar("SELECT EXISTS (SELECT 1 FROM robot_groups WHERE tweet_id = $1)")
+            .bind(tweet_id)
+            .fetch_one(&mut tx)
+            .await?;
+
+        if exists {
+            return Err(anyhow!("the robots of tweet {} have already been stored", tweet_id));
+        }
+    }
+
+    let platform = match tweet_id {
+        Some(_) => Platform::Twitter,
+        None => Platform::Manual,
+    };
+
+    let group_id: i32 = sqlx::query_scalar(
+        "INSERT INTO robot_groups \
+            (platform, source_ref, tweet_time, image_url, body, alt, content_warnings) \
+        VALUES \
+            ($1, $2, $3, $4, $5, $6, $7) \
+        RETURNING id"
+    )
+    .bind(platform)
+    .bind(tweet_id.map(|tweet_id| tweet_id.to_string()))
+    .bind(opts.time.unwrap_or_else(Utc::now))
+    .bind(&opts.image_url)
+    .bind(body)
+    .bind(alt)
+    .bind(&content_warnings)
+    .fetch_one(&mut tx)
+    .await
+    .with_context(|| format!("failed to add group for robot {}", id))?;
+
+    let res = sqlx::query(
+        "INSERT INTO robots (id, group_id, prefix, suffix, plural) \
+        VALUES ($1, $2, $3, $4, $5) \
+        ON CONFLICT (id) DO NOTHING"
+    )
+    .bind(&id)
+    .bind(group_id)
+    .bind(name.prefix.as_ref())
+    .bind(name.suffix.as_ref())
+    .bind(name.plural.as_deref())
+    .execute(&mut tx)
+    .await
+    .with_context(|| format!("failed to add robot {}", id))?;
+
+    if res.rows_affected() == 0 {
+        return Err(anyhow!("robot {} already exists", id));
+    }
+
+    let media = MediaEntry {
+        media_type: "photo",
+        url: &opts.image_url,
+        alt,
+        duration_millis: None,
+        variants: Vec::new(),
+    };
+
+    scribe::store_group_media(&mut tx, group_id, &[media])
+        .await
+        .with_context(|| format!("failed to store image for robot {}", id))?;
+
+    let new_values = snapshot(&mut tx, &id).await?;
+    record_edit(&mut tx, &id, "add", editor, None, Some(new_values)).await?;
+
+    tx.commit().await?;
+
+    println!("{}", id);
+
+    Ok(())
+}
+
+async fn edit(db_conn: &mut PgConnection, editor: &str, opts: EditOpts) -> anyhow::Result<()> {
+    let mut tx = db_conn.begin().await?;
+
+    let id = ident::resolve(&mut tx, &opts.id).await?;
+
+    let old_robot = sqlx::query_as::<_, RobotRecord>(
+        "SELECT id, group_id, prefix, suffix, plural, needs_review FROM robots WHERE id = $1 FOR UPDATE"
+    )
+    .bind(&id)
+    .fetch_optional(&mut tx)
+    .await
+    .with_context(|| format!("failed to get robot {}", id))?
+    .ok_or_else(|| anyhow!("robot {} does not exist", id))?;
+
+    let old_group = sqlx::query_as::<_, RobotGroup>(
+        "SELECT id, tweet_id, tweet_time, image_url, body, alt, content_warnings FROM robot_groups \
+        WHERE id = $1 FOR UPDATE"
+    )
+    .bind(old_robot.group_id)
+    .fetch_one(&mut tx)
+    .await
+    .with_context(|| format!("failed to get group of robot {}", id))?;
+
+    let mut robot = old_robot.clone();
+    let mut group = old_group.clone();
+
+    if let Some(prefix) = opts.prefix {
+        robot.prefix = prefix.trim().to_owned();
+    }
+    if let Some(suffix) = opts.suffix {
+        robot.suffix = suffix.trim().to_owned();
+    }
+    if let Some(plural) = opts.plural {
+        robot.plural = Some(plural.trim().to_owned()).filter(|plural| !plural.is_empty());
+    } else if opts.no_plural {
+        robot.plural = None;
+    }
+    if opts.reviewed {
+        robot.needs_review = false;
+    } else if opts.needs_review {
+        robot.needs_review = true;
+    }
+    if let Some(body) = opts.body {
+        group.body = body.trim().to_owned();
+    }
+    if !opts.content_warnings.is_empty() {
+        group.content_warnings = normalise_cws(&opts.content_warnings);
+    } else if opts.no_cws {
+        group.content_warnings.clear();
+    }
+
+    let number = opts.number.unwrap_or(id.number);
+    robot.id = robot_ident(number, &robot_name(&robot.prefix, &robot.suffix, robot.plural.as_deref()))?;
+
+    if robot == old_robot && group == old_group {
+        eprintln!("no changes to robot {}", id);
+        return Ok(());
+    }
+
+    if robot.id != old_robot.id {
+        let exists: bool = sqlx::query_scalar("SELECT EXISTS (SELECT 1 FROM robots WHERE id = $1)")
+            .bind(&robot.id)
+            .fetch_one(&mut tx)
+            .await?;
+
+        if exists {
+            return Err(anyhow!("cannot rename {} to {}, since {} already exists", old_robot.id, robot.id, robot.id));
+        }
+    }
+
+    let old_values = snapshot(&mut tx, &old_robot.id).await?;
+
+    // Renaming the robot also renames it in the tables which reference it, since their foreign keys
+    // are declared ON UPDATE CASCADE
+    sqlx::query(
+        "UPDATE robots SET id = $2, prefix = $3, suffix = $4, plural = $5, needs_review = $6 \
+        WHERE id = $1"
+    )
+    .bind(&old_robot.id)
+    .bind(&robot.id)
+    .bind(&robot.prefix)
+    .bind(&robot.suffix)
+    .bind(robot.plural.as_deref())
+    .bind(robot.needs_review)
+    .execute(&mut tx)
+    .await
+    .with_context(|| format!("failed to update robot {}", old_robot.id))?;
+
+    if group != old_group {
+        sqlx::query("UPDATE robot_groups SET body = $2, content_warnings = $3 WHERE id = $1")
+            .bind(group.id)
+            .bind(&group.body)
+            .bind(&group.content_warnings)
+            .execute(&mut tx)
+            .await
+            .with_context(|| format!("failed to update group of robot {}", old_robot.id))?;
+    }
+
+    if robot.id != old_robot.id {
+        // Keep the old id as an alias, so that it can still be used to refer to the robot. Aliases
+        // which pointed to the old id now point to the new one, as the foreign key cascades
+        sqlx::query("DELETE FROM robot_aliases WHERE alias = $1")
+            .bind(&robot.id)
+            .execute(&mut tx)
+            .await?;
+
+        sqlx::query("INSERT INTO robot_aliases (alias, robot_id) VALUES ($1, $2)")
+            .bind(&old_robot.id)
+            .bind(&robot.id)
+            .execute(&mut tx)
+            .await
+            .with_context(|| format!("failed to add alias {} for robot {}", old_robot.id, robot.id))?;
+    }
+
+    let new_values = snapshot(&mut tx, &robot.id).await?;
+    record_edit(&mut tx, &robot.id, "edit", editor, Some(old_values), Some(new_values)).await?;
+
+    tx.commit().await?;
+
+    println!("{}", robot.id);
+
+    Ok(())
+}
+
+async fn remove(db_conn: &mut PgConnection, editor: &str, opts: RemoveOpts) -> anyhow::Result<()> {
+    let mut tx = db_conn.begin().await?;
+
+    let id = ident::resolve(&mut tx, &opts.id).await?;
+
+    let (past_dailies, scheduled_dailies): (i64, i64) = sqlx::query_as(
+        "SELECT \
+            (SELECT count(*) FROM past_dailies WHERE robot_id = $1), \
+            (SELECT count(*) FROM scheduled_dailies WHERE robot_id = $1)"
+    )
+    .bind(&id)
+    .fetch_one(&mut tx)
+    .await?;
+
+    // Deleting the robot would also delete its dailies, which would lose the record of it having
+    // been posted and silently unschedule it
+    if (past_dailies > 0 || scheduled_dailies > 0) && !opts.force {
+        return Err(anyhow!(
+            "robot {} has {} past and {} scheduled daily posts; use --force to remove it and them",
+            id, past_dailies, scheduled_dailies
+        ));
+    }
+
+    let exists: bool = sqlx::query_scalar("SELECT EXISTS (SELECT 1 FROM robots WHERE id = $1)")
+        .bind(&id)
+        .fetch_one(&mut tx)
+        .await?;
+
+    if !exists {
+        return Err(anyhow!("robot {} does not exist", id));
+    }
+
+    let old_values = snapshot(&mut tx, &id).await?;
+
+    let group_id: i32 = sqlx::query_scalar("DELETE FROM robots WHERE id = $1 RETURNING group_id")
+        .bind(&id)
+        .fetch_one(&mut tx)
+        .await
+        .with_context(|| format!("failed to remove robot {}", id))?;
+
+    sqlx::query(
+        "DELETE FROM robot_groups WHERE id = $1 AND NOT EXISTS (SELECT 1 FROM robots WHERE group_id = $1)"
+    )
+    .bind(group_id)
+    .execute(&mut tx)
+    .await
+    .with_context(|| format!("failed to remove group of robot {}", id))?;
+
+    record_edit(&mut tx, &id, "remove", editor, Some(old_values), None).await?;
+
+    tx.commit().await?;
+
+    Ok(())
+}
+
+async fn alias(db_conn: &mut PgConnection, editor: &str, opts: AliasOpts) -> anyhow::Result<()> {
+    let mut tx = db_conn.begin().await?;
+
+    let id = ident::resolve(&mut tx, &opts.robot).await?;
+    let alias = ident::parse_ident(&opts.alias)?;
+
+    // Robot ids take precedence over aliases, so an alias with the same id as a robot would never
+    // be used
+    let exists: bool = sqlx::query_scalar("SELECT EXISTS (SELECT 1 FROM robots WHERE id = $1)")
+        .bind(&alias)
+        .fetch_one(&mut tx)
+        .await?;
+
+    if exists {
+        return Err(anyhow!("cannot add alias {}, since a robot with that id already exists", alias));
+    }
+
+    let old_robot_id: Option<IdentBuf> = sqlx::query_scalar(
+        "SELECT robot_id FROM robot_aliases WHERE alias = $1 FOR UPDATE"
+    )
+    .bind(&alias)
+    .fetch_optional(&mut tx)
+    .await?;
+
+    if let Some(old_robot_id) = old_robot_id {
+        return Err(anyhow!("{} is already an alias of robot {}", alias, old_robot_id));
+    }
+
+    sqlx::query("INSERT INTO robot_aliases (alias, robot_id) VALUES ($1, $2)")
+        .bind(&alias)
+        .bind(&id)
+        .execute(&mut tx)
+        .await
+        .with_context(|| format!("failed to add alias {} for robot {}", alias, id))?;
+
+    let new_values = Json(serde_json::json!({ "alias": alias.to_string() }));
+    record_edit(&mut tx, &id, "alias", editor, None, Some(new_values)).await?;
+
+    tx.commit().await?;
+
+    println!("{}", id);
+
+    Ok(())
+}
+
+async fn unalias(db_conn: &mut PgConnection, editor: &str, opts: UnaliasOpts) -> anyhow::Result<()> {
+    let mut tx = db_conn.begin().await?;
+
+    let alias = ident::parse_ident(&opts.alias)?;
+
+    let id: IdentBuf = sqlx::query_scalar("DELETE FROM robot_aliases WHERE alias = $1 RETURNING robot_id")
+        .bind(&alias)
+        .fetch_optional(&mut tx)
+        .await
+        .with_context(|| format!("failed to remove alias {}", alias))?
+        .ok_or_else(|| anyhow!("{} is not an alias", alias))?;
+
+    let old_values = Json(serde_json::json!({ "alias": alias.to_string() }));
+    record_edit(&mut tx, &id, "unalias", editor, Some(old_values), None).await?;
+
+    tx.commit().await?;
+
+    Ok(())
+}
+
+/// Returns the robot and its group as json, for recording in `robot_edits`.
+async fn snapshot(db_conn: &mut PgConnection, id: &IdentBuf) -> sqlx::Result<Json<serde_json::Value>> {
+    sqlx::query_scalar(
+        "SELECT to_jsonb(r) || jsonb_build_object('group', to_jsonb(g)) \
+        FROM robots r \
+        JOIN robot_groups g ON g.id = r.group_id \
+        WHERE r.id = $1"
+    )
+    .bind(id)
+    .fetch_one(db_conn)
+    .await
+}
+
+async fn record_edit(
+    db_conn: &mut PgConnection,
+    id: &IdentBuf,
+    action: &str,
+    editor: &str,
+    old_values: Option<Json<serde_json::Value>>,
+    new_values: Option<Json<serde_json::Value>>,
+) -> anyhow::Result<()>
+{
+    sqlx::query(
+        "INSERT INTO robot_edits (robot_id, action, edited_by, old_values, new_values) \
+        VALUES ($1, $2, $3, $4, $5)"
+    )
+    .bind(id)
+    .bind(action)
+    .bind(editor)
+    .bind(old_values)
+    .bind(new_values)
+    .execute(db_conn)
+    .await
+    .with_context(|| format!("failed to record edit of robot {}", id))
+    .map(|_| ())
+}
+
+/// Builds a robot name from the parts given on the command line. Surrounding whitespace is removed,
+/// since it would otherwise be stored in the robot's displayed name without appearing in its id, and
+/// an empty plural is treated as no plural.
+fn robot_name<'a>(prefix: &'a str, suffix: &'a str, plural: Option<&'a str>) -> RobotName<'a> {
+    RobotName {
+        prefix: Cow::Borrowed(prefix.trim()),
+        suffix: Cow::Borrowed(suffix.trim()),
+        plural: plural
+            .map(str::trim)
+            .filter(|plural| !plural.is_empty())
+            .map(Cow::Borrowed),
+    }
+}
+
+/// Returns the id that the robot would be stored under if it had been parsed from a tweet, checking
+/// that its name is valid.
+fn robot_ident(number: i32, name: &RobotName) -> anyhow::Result<IdentBuf> {
+    if name.suffix.trim().is_empty() {
+        return Err(anyhow!("robot name suffix must not be empty"));
+    }
+
+    let robot = Robot {
+        number,
+        name: name.clone(),
+    };
+
+    let id = robot.ident();
+
+    if id.name.is_empty() {
+        return Err(anyhow!(r#"robot name prefix "{}" contains no letters or digits"#, name.prefix));
+    }
+
+    Ok(id)
+}
+
+fn normalise_cws(content_warnings: &[String]) -> Vec<String> {
+    let mut normalised = Vec::<String>::new();
+    for cw in content_warnings.iter().filter_map(|cw| parse::normalise_cw(cw)) {
+        if !normalised.contains(&cw) {
+            normalised.push(cw);
+        }
+    }
+    normalised
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{robot_ident, robot_name};
+
+    #[test]
+    fn test_robot_name_trimmed() {
+        let name = robot_name("  Tea ", " bot\n", Some(" s "));
+        assert_eq!(name.prefix, "Tea");
+        assert_eq!(name.suffix, "bot");
+        assert_eq!(name.plural.as_deref(), Some("s"));
+        assert_eq!(robot_ident(1, &name).unwrap().to_string(), "1/tea");
+
+        let name = robot_name("Mischief", "bot", Some("  "));
+        assert_eq!(name.plural, None);
+
+        let name = robot_name("Salt ", "bot", None);
+        assert_eq!(name.prefix, "Salt");
+        assert_eq!(robot_ident(558, &name).unwrap().to_string(), "558/salt");
+    }
+
+    #[test]
+    fn test_robot_ident_invalid() {
+        assert!(robot_ident(1, &robot_name("Tea", "  ", None)).is_err());
+        assert!(robot_ident(1, &robot_name(" !? ", "bot", None)).is_err());
+    }
+}
@@ -3,25 +3,289 @@ use std::error;
 use std::fmt;
 
 use chrono::{Utc, DateTime};
-use goldcrest::data::{Tweet, Media};
+use goldcrest::data::Tweet;
 use goldcrest::data::tweet::TweetTextOptions;
+use serde::Serialize;
 use sqlx::Connection;
 use sqlx::postgres::PgConnection;
+use sqlx::types::Json;
 
-use crate::model::IdentBuf;
-use crate::parse::{self, Robot};
+use crate::model::{IdentBuf, Platform, RawMedia};
+use crate::overrides::ParseOverrides;
+use crate::parse::{self, ParseError, ParseWarning, Robot};
 use crate::plural::Plural;
 
 #[derive(Clone, Debug)]
 struct RobotTweetData<'a> {
-    tweet_id: i64,
+    source: &'a PostSource,
     tweet_time: DateTime<Utc>,
     image_url: &'a str,
     body: &'a str,
     alt: Option<&'a str>,
-    cw: Option<&'a str>,
+    content_warnings: &'a [String],
+    needs_review: bool,
+    media: &'a [MediaEntry<'a>],
 }
 
+/// A media item of a robot tweet, as stored in the `robot_media` table.
+#[derive(Clone, Debug)]
+pub(crate) struct MediaEntry<'a> {
+    pub(crate) media_type: &'a str,
+    /// For animated gifs and videos, this is the url of the poster frame.
+    pub(crate) url: &'a str,
+    pub(crate) alt: Option<&'a str>,
+    pub(crate) duration_millis: Option<u64>,
+    pub(crate) variants: Vec<VariantEntry<'a>>,
+}
+
+/// One of the encodings that an animated gif or video is available in.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct VariantEntry<'a> {
+    pub(crate) content_type: &'a str,
+    pub(crate) bitrate: Option<u64>,
+    pub(crate) url: &'a str,
+}
+
+impl<'a> MediaEntry<'a> {
+    fn new(media_type: &'a str, url: &'a str, alt: &'a str) -> Self {
+        let alt = alt.trim();
+
+        Self {
+            media_type,
+            url,
+            alt: if alt.is_empty() { None } else { Some(alt) },
+            duration_millis: None,
+            variants: Vec::new(),
+        }
+    }
+
+    pub(crate) fn from_raw_media(media: &'a RawMedia) -> Self {
+        let mut entry = Self::new(&media.media_type, &media.media_url, &media.alt);
+
+        if let Some(ref video_info) = media.video_info {
+            entry.duration_millis = video_info.duration_millis;
+            entry.variants = video_info.variants
+                .iter()
+                .map(|variant| VariantEntry {
+                    content_type: &variant.content_type,
+                    bitrate: variant.bitrate,
+                    url: &variant.url,
+                })
+                .collect();
+        }
+
+        entry
+    }
+}
+
+/// Options controlling how robot tweets are scribed.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct ScribeOptions {
+    /// Print the reason for each tweet that is skipped or flagged for review to stderr.
+    pub(crate) verbose: bool,
+
+    /// Skip tweets which parsed with warnings, rather than storing them flagged for review.
+    pub(crate) strict: bool,
+
+    /// Update robots which are already stored, rather than skipping them as duplicates. Robots which
+    /// are no longer parsed from an updated post are flagged for review.
+    pub(crate) update: bool,
+}
+
+/// What happened to a robot when it was stored.
+#[derive(Serialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum StoreOutcome {
+    Inserted,
+    Updated,
+    Unchanged,
+    /// The robot was stored from an earlier version of the post but is no longer parsed from it,
+    /// so it was flagged for review.
+    Flagged,
+}
+
+impl fmt::Display for StoreOutcome {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Inserted => write!(f, "inserted"),
+            Self::Updated => write!(f, "updated"),
+            Self::Unchanged => write!(f, "unchanged"),
+            Self::Flagged => write!(f, "flagged"),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub(crate) struct StoredRobot {
+    pub(crate) id: IdentBuf,
+    pub(crate) outcome: StoreOutcome,
+}
+
+/// The result of scribing a single post: either the robots that were stored, or the reason that
+/// the post was skipped.
+#[derive(Debug)]
+pub(crate) struct ScribedPost {
+    pub(crate) source: PostSource,
+    pub(crate) result: Result<Vec<StoredRobot>, InvalidTweet>,
+}
+
+/// Returns all of the robots stored from the given tweets, in order.
+pub(crate) fn stored_robots(tweets: &[ScribedPost]) -> Vec<StoredRobot> {
+    tweets
+        .iter()
+        .filter_map(|tweet| tweet.result.as_ref().ok())
+        .flatten()
+        .cloned()
+        .collect()
+}
+
+/// Where a robot post was published, and the id it was published under.
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+pub(crate) enum PostSource {
+    Tweet(u64),
+    /// The id of an ActivityPub object, which is a url.
+    ActivityPub(String),
+}
+
+impl PostSource {
+    pub(crate) fn platform(&self) -> Platform {
+        match self {
+            Self::Tweet(_) => Platform::Twitter,
+            Self::ActivityPub(_) => Platform::ActivityPub,
+        }
+    }
+
+    /// The id of the post on its platform, as stored in the `source_ref` column of `robot_groups`.
+    pub(crate) fn source_ref(&self) -> String {
+        match self {
+            Self::Tweet(tweet_id) => tweet_id.to_string(),
+            Self::ActivityPub(object_id) => object_id.clone(),
+        }
+    }
+
+    pub(crate) fn tweet_id(&self) -> Option<u64> {
+        match self {
+            Self::Tweet(tweet_id) => Some(*tweet_id),
+            Self::ActivityPub(_) => None,
+        }
+    }
+}
+
+impl fmt::Display for PostSource {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Tweet(tweet_id) => write!(f, "tweet {}", tweet_id),
+            Self::ActivityPub(object_id) => write!(f, "post {}", object_id),
+        }
+    }
+}
+
+/// A robot post in the form that is parsed and stored, independent of where it came from. Posts are
+/// built from Tweets returned by the Twitter API, read from an offline archive or read from an
+/// ActivityPub outbox.
+#[derive(Clone, Debug)]
+pub(crate) struct RobotPost {
+    pub(crate) source: PostSource,
+    pub(crate) time: DateTime<Utc>,
+    /// The full text of the post, including the media links and urls which are left out of `text`.
+    pub(crate) full_text: String,
+    /// The text given to the parser, which leaves out media links and urls.
+    pub(crate) text: String,
+    /// Content warnings given by the platform rather than in the text, which are added to those
+    /// found by the parser.
+    pub(crate) content_warnings: Vec<String>,
+    pub(crate) media: Vec<RawMedia>,
+    pub(crate) hashtags: Vec<String>,
+    pub(crate) mentions: Vec<PostMention>,
+    pub(crate) urls: Vec<PostUrl>,
+}
+
+#[derive(Clone, Debug)]
+pub(crate) struct PostMention {
+    pub(crate) user_id: u64,
+    pub(crate) handle: String,
+    pub(crate) display_name: String,
+}
+
+#[derive(Clone, Debug)]
+pub(crate) struct PostUrl {
+    pub(crate) url: String,
+    pub(crate) expanded_url: String,
+    pub(crate) display_url: String,
+    /// The range of character indices of the url in the full text of the post.
+    pub(crate) indices: Option<[usize; 2]>,
+}
+
+impl RobotPost {
+    /// Converts a tweet to a post. If the tweet is a retweet, the original tweet is used.
+    pub(crate) fn from_tweet(tweet: &Tweet) -> Self {
+        let tweet = tweet_original(tweet);
+        let full_text = tweet.text(TweetTextOptions::all());
+
+        Self {
+            source: PostSource::Tweet(tweet.id),
+            time: tweet.created_at,
+            text: tweet.text(TEXT_OPTIONS),
+            content_warnings: Vec::new(),
+            media: tweet.media
+                .iter()
+                .map(|media| RawMedia {
+                    indices: link_indices(&full_text, &media.url),
+                    ..RawMedia::from(media)
+                })
+                .collect(),
+            hashtags: tweet.hashtags
+                .iter()
+                .map(|hashtag| hashtag.text.clone())
+                .collect(),
+            mentions: tweet.mentions
+                .iter()
+                .map(|mention| PostMention {
+                    user_id: mention.user_id,
+                    handle: mention.handle.name_only.clone(),
+                    display_name: mention.display_name.clone(),
+                })
+                .collect(),
+            urls: tweet.urls
+                .iter()
+                .map(|url| PostUrl {
+                    url: url.url.clone(),
+                    expanded_url: url.expanded_url.clone(),
+                    display_url: url.display_url.clone(),
+                    indices: link_indices(&full_text, &url.url),
+                })
+                .collect(),
+            full_text,
+        }
+    }
+}
+
+/// Finds the range of character indices of the given link in the text, in the same form as the
+/// entity indices given by Twitter. The link must be followed by whitespace or the end of the text,
+/// so that a link is not found at the start of a longer one.
+fn link_indices(text: &str, link: &str) -> Option<[usize; 2]> {
+    let (start, _) = text
+        .match_indices(link)
+        .find(|&(start, _)| text[start + link.len()..].chars().next().is_none_or(char::is_whitespace))?;
+
+    let start = text[..start].chars().count();
+    Some([start, start + link.chars().count()])
+}
+
+/// Removes the characters in each of the given ranges of character indices from the text.
+pub(crate) fn remove_char_ranges(text: &str, ranges: &[[usize; 2]]) -> String {
+    text.chars()
+        .enumerate()
+        .filter(|(i, _)| !ranges.iter().any(|&[start, end]| (start..end).contains(i)))
+        .map(|(_, c)| c)
+        .collect()
+}
+
+/// The entities included in the tweet text passed to the parser.
+pub(crate) const TEXT_OPTIONS: TweetTextOptions = TweetTextOptions::all()
+    .media(false)
+    .urls(false);
+
 pub(crate) fn tweet_original(mut tweet: &Tweet) -> &Tweet {
     while let Some(ref retweeted) = tweet.retweeted {
         tweet = retweeted.as_ref();
@@ -29,141 +293,816 @@ pub(crate) fn tweet_original(mut tweet: &Tweet) -> &Tweet {
     tweet
 }
 
-/// Parses and stores a collection of tweets in series, skipping any tweets that are not valid
-/// small robots.
+/// Prints the ids of the inserted and updated robots to stdout, so that they can be piped into
+/// other commands such as `sbb image`. If `update` is set, the outcome for every robot and the
+/// total number of each outcome are also printed to stderr.
+pub(crate) fn report_stored(robots: &[StoredRobot], update: bool) {
+    let (mut inserted, mut updated, mut unchanged, mut flagged) = (0usize, 0usize, 0usize, 0usize);
+
+    for robot in robots {
+        match robot.outcome {
+            StoreOutcome::Inserted => inserted += 1,
+            StoreOutcome::Updated => updated += 1,
+            StoreOutcome::Unchanged => unchanged += 1,
+            StoreOutcome::Flagged => flagged += 1,
+        }
+
+        if matches!(robot.outcome, StoreOutcome::Inserted | StoreOutcome::Updated) {
+            println!("{}", robot.id);
+        }
+
+        if update {
+            eprintln!("{} {}", robot.outcome, robot.id);
+        }
+    }
+
+    if update {
+        eprintln!(
+            "{} inserted, {} updated, {} unchanged, {} flagged",
+            inserted, updated, unchanged, flagged
+        );
+    }
+}
+
+/// Parses and stores a collection of tweets in series. Any tweets that are not valid small robots
+/// are skipped and stored in the quarantine table, so that they can be reviewed later.
 pub(crate) async fn scribe_tweets(
     db_conn: &mut PgConnection,
     tweets: &[Tweet],
-    verbose: bool
-) -> Result<Vec<IdentBuf>, ScribeFailure>
+    overrides: &ParseOverrides,
+    opts: ScribeOptions
+) -> Result<Vec<ScribedPost>, ScribeFailure>
+{
+    let posts = tweets
+        .iter()
+        .map(RobotPost::from_tweet)
+        .collect::<Vec<_>>();
+
+    scribe_posts(db_conn, &posts, overrides, opts).await
+}
+
+/// Parses and stores a collection of posts in series, in the same way as `scribe_tweets`.
+pub(crate) async fn scribe_posts(
+    db_conn: &mut PgConnection,
+    posts: &[RobotPost],
+    overrides: &ParseOverrides,
+    opts: ScribeOptions
+) -> Result<Vec<ScribedPost>, ScribeFailure>
 {
-    let mut group_ids = Vec::new();
+    let mut scribed = Vec::with_capacity(posts.len());
+    scribe_posts_into(db_conn, posts, overrides, opts, &mut scribed).await?;
+    Ok(scribed)
+}
+
+/// Parses and stores a collection of posts in series, pushing the result of each post to `scribed`
+/// in the same order as the posts. Each post is stored in its own transaction, so if an error
+/// occurs, the posts whose results were pushed before it have still been stored.
+pub(crate) async fn scribe_posts_into(
+    db_conn: &mut PgConnection,
+    posts: &[RobotPost],
+    overrides: &ParseOverrides,
+    opts: ScribeOptions,
+    scribed: &mut Vec<ScribedPost>,
+) -> Result<(), ScribeFailure>
+{
+    for post in posts {
+        match scribe_post(db_conn, post, overrides, opts).await {
+            Ok(robots) => scribed.push(ScribedPost {
+                source: post.source.clone(),
+                result: Ok(robots.into_iter().collect()),
+            }),
 
-    for tweet in tweets {
-        let tweet_id = tweet.id;
+            Err(NotScribed::InvalidTweet(err)) => {
+                if opts.verbose {
+                    eprintln!("skip {}: {}", post.source, err);
+                }
 
-        match scribe_tweet(db_conn, tweet).await {
-            Ok(robot_ids) => group_ids.extend(robot_ids.into_iter()),
+                // The raw tweet is kept even though it was not stored, so that it can be reparsed
+                // once the parser is able to handle it
+                if let Some(tweet_id) = post.source.tweet_id() {
+                    store_raw_tweet(db_conn, tweet_id, post).await?;
+                }
 
-            Err(NotScribed::InvalidTweet(err)) => if verbose {
-                eprintln!("skip tweet {}: {}", tweet_id, err);
+                // Tweets which have already been stored are not a problem that needs reviewing. The
+                // quarantine only holds tweets, so other posts are reported but not kept.
+                let quarantine_id = post.source
+                    .tweet_id()
+                    .filter(|_| !matches!(err, InvalidTweet::AlreadyStored));
+
+                if let Some(tweet_id) = quarantine_id {
+                    store_quarantined(db_conn, tweet_id, post, &err).await?;
+                }
+
+                scribed.push(ScribedPost {
+                    source: post.source.clone(),
+                    result: Err(err),
+                });
             },
 
             Err(NotScribed::ScribeFailure(err)) => return Err(err)
         }
     }
 
-    Ok(group_ids)
+    Ok(())
 }
 
-/// Parses the given tweet, adds it to the database and returns the id of the new robot group.
-pub(crate) async fn scribe_tweet(
+/// Parses the given post, adds it to the database and returns the ids of the stored robots. If
+/// the post has a parse override, the override is used instead of parsing the post text.
+pub(crate) async fn scribe_post(
     db_conn: &mut PgConnection,
-    tweet: &Tweet
-) -> Result<Plural<IdentBuf>, NotScribed>
+    post: &RobotPost,
+    overrides: &ParseOverrides,
+    opts: ScribeOptions
+) -> Result<Plural<StoredRobot>, NotScribed>
 {
-    const TEXT_OPTIONS: TweetTextOptions = TweetTextOptions::all()
-        .media(false)
-        .urls(false);
+    let tweet_text = &post.text;
 
-    let tweet = tweet_original(tweet);
-    let tweet_text = tweet.text(TEXT_OPTIONS);
+    let parse_override = post.source
+        .tweet_id()
+        .and_then(|tweet_id| overrides.get(tweet_id));
 
-    let group = match parse::parse_group(&tweet_text) {
-        Some(group) if !group.robots.is_empty() => group,
-        _ => return Err(InvalidTweet::ParseUnsuccessful.into()),
-    };
+    let mut group = match parse_override {
+        Some(parse_override) => parse_override.group(),
 
-    let body = group.body.trim();
+        None => match parse::parse_group(tweet_text) {
+            Ok(group) => group,
+            Err(err) => return Err(InvalidTweet::ParseUnsuccessful {
+                context: err.context(tweet_text).to_owned(),
+                error: err,
+            }.into()),
+        },
+    };
 
-    let media = {
-        let media = tweet.media
-            .iter()
-            .find(|media| is_valid_robot_media(media));
+    let needs_review = !group.warnings.is_empty();
 
-        match media {
-            Some(media) => media,
-            None => return Err(InvalidTweet::MissingMedia.into()),
+    if needs_review {
+        if opts.strict {
+            return Err(InvalidTweet::ParseWarnings(group.warnings).into());
         }
-    };
 
-    let media_url = media.media_url.as_str();
+        if opts.verbose {
+            for warning in &group.warnings {
+                eprintln!("{} flagged for review: {}", post.source, warning);
+            }
+        }
+    }
 
-    let alt = {
-        let alt = media.alt.trim();
-        if alt.is_empty() {
-            None
-        } else {
-            Some(alt)
+    for content_warning in post.content_warnings.iter().filter_map(|cw| parse::normalise_cw(cw)) {
+        if !group.content_warnings.contains(&content_warning) {
+            group.content_warnings.push(content_warning);
         }
+    }
+
+    let body = group.body.trim();
+
+    let media = post.media
+        .iter()
+        .filter(|media| is_valid_robot_media_type(&media.media_type))
+        .map(MediaEntry::from_raw_media)
+        .collect::<Vec<_>>();
+
+    // The first media item is the robot's primary image
+    let primary_media = match media.first() {
+        Some(media) => media,
+        None => return Err(InvalidTweet::MissingMedia.into()),
     };
 
     let tweet_data = RobotTweetData {
-        tweet_id: tweet.id as i64,
-        tweet_time: tweet.created_at,
-        image_url: media_url,
+        source: &post.source,
+        tweet_time: post.time,
+        image_url: primary_media.url,
         body: body,
-        alt: alt,
-        cw: group.cw,
+        alt: primary_media.alt,
+        content_warnings: &group.content_warnings,
+        needs_review,
+        media: &media,
     };
 
-    match group.robots.as_slice() {
-        [] => Err(InvalidTweet::NoRobots.into()),
+    if group.robots.is_empty() {
+        return Err(InvalidTweet::NoRobots.into());
+    }
+
+    let mut tx = db_conn.begin().await?;
+
+    let stored_group = store_group(&mut tx, &tweet_data, opts.update).await?;
 
-        [robot] => store_robot(db_conn, robot, &tweet_data)
+    let robot_ids = match group.robots.as_slice() {
+        [robot] => store_robot(&mut tx, robot, &stored_group, &tweet_data, opts.update)
             .await
-            .map(Plural::One),
+            .map(Plural::One)?,
 
         robots => {
             let mut robot_ids = Vec::with_capacity(robots.len());
-            let mut tx = db_conn.begin().await?;
             for robot in robots {
-                robot_ids.push(store_robot(&mut tx, robot, &tweet_data).await?);
+                robot_ids.push(store_robot(&mut tx, robot, &stored_group, &tweet_data, opts.update).await?);
             }
-            tx.commit().await?;
-            Ok(Plural::Many(robot_ids))
+            Plural::Many(robot_ids)
         },
+    };
+
+    // Robots stored from an earlier version of the tweet which are no longer parsed from it are
+    // flagged for review rather than removed, in the same way as by reparse
+    let robot_ids = match stored_group.outcome {
+        StoreOutcome::Inserted => robot_ids,
+        _ => match flag_missing_robots(&mut tx, stored_group.id, &robot_ids).await? {
+            flagged if flagged.is_empty() => robot_ids,
+            flagged => Plural::Many(robot_ids.into_iter().chain(flagged).collect()),
+        },
+    };
+
+    // The entity, raw tweet and quarantine tables are only for tweets
+    if let Some(tweet_id) = post.source.tweet_id() {
+        store_entities(&mut tx, tweet_id, post).await?;
+        store_raw_tweet(&mut tx, tweet_id, post).await?;
+
+        sqlx::query("DELETE FROM quarantine WHERE tweet_id = $1")
+            .bind(tweet_id as i64)
+            .execute(&mut tx)
+            .await?;
+    }
+
+    tx.commit().await?;
+
+    Ok(robot_ids)
+}
+
+/// The robot group that the robots of a tweet are stored in.
+#[derive(Clone, Copy, Debug)]
+struct StoredGroup {
+    id: i32,
+    outcome: StoreOutcome,
+}
+
+/// Inserts the group of robots for the tweet into the database, along with its media. If the tweet
+/// has already been stored and `update` is set, the existing group is updated instead.
+async fn store_group(
+    db_conn: &mut PgConnection,
+    tweet_data: &RobotTweetData<'_>,
+    update: bool,
+) -> Result<StoredGroup, NotScribed>
+{
+    let inserted_id: Option<i32> = sqlx::query_scalar(
+        "INSERT INTO robot_groups \
+            (platform, source_ref, tweet_time, image_url, body, alt, content_warnings) \
+        VALUES \
+            ($1, $2, $3, $4, $5, $6, $7) \
+        ON CONFLICT (platform, source_ref) DO NOTHING \
+        RETURNING id"
+    )
+    .bind(tweet_data.source.platform())
+    .bind(tweet_data.source.source_ref())
+    .bind(tweet_data.tweet_time)
+    .bind(tweet_data.image_url)
+    .bind(tweet_data.body)
+    .bind(tweet_data.alt)
+    .bind(tweet_data.content_warnings)
+    .fetch_optional(&mut *db_conn)
+    .await?;
+
+    if let Some(id) = inserted_id {
+        store_group_media(&mut *db_conn, id, tweet_data.media).await?;
+        return Ok(StoredGroup { id, outcome: StoreOutcome::Inserted });
+    }
+
+    if !update {
+        return Err(InvalidTweet::AlreadyStored.into());
+    }
+
+    let id: i32 = sqlx::query_scalar(
+        "SELECT id FROM robot_groups WHERE platform = $1 AND source_ref = $2 FOR UPDATE"
+    )
+    .bind(tweet_data.source.platform())
+    .bind(tweet_data.source.source_ref())
+    .fetch_one(&mut *db_conn)
+    .await?;
+
+    let changed = update_group(&mut *db_conn, id, &GroupValues {
+        tweet_time: tweet_data.tweet_time,
+        image_url: tweet_data.image_url,
+        body: tweet_data.body,
+        alt: tweet_data.alt,
+        content_warnings: tweet_data.content_warnings,
+    })
+    .await?;
+
+    let media_changed = store_group_media(&mut *db_conn, id, tweet_data.media).await?;
+
+    let outcome = match changed || media_changed {
+        true => StoreOutcome::Updated,
+        false => StoreOutcome::Unchanged,
+    };
+
+    Ok(StoredGroup { id, outcome })
+}
+
+/// The values of a robot group which are taken from its post.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct GroupValues<'a> {
+    pub(crate) tweet_time: DateTime<Utc>,
+    pub(crate) image_url: &'a str,
+    pub(crate) body: &'a str,
+    pub(crate) alt: Option<&'a str>,
+    pub(crate) content_warnings: &'a [String],
+}
+
+/// Updates an existing robot group with the given values, recording its previous values in
+/// `robot_group_history` first. Nothing is written if none of the values have changed. Returns
+/// whether or not the group changed.
+pub(crate) async fn update_group(
+    db_conn: &mut PgConnection,
+    group_id: i32,
+    values: &GroupValues<'_>,
+) -> sqlx::Result<bool>
+{
+    // Record the previous values of the group, but only if any of them are about to change
+    let res = sqlx::query(
+        "INSERT INTO robot_group_history \
+            (group_id, tweet_time, image_url, body, alt, content_warnings) \
+        SELECT \
+            id, tweet_time, image_url, body, alt, content_warnings \
+        FROM robot_groups \
+        WHERE id = $1 \
+            AND (tweet_time, image_url, body, alt, content_warnings) \
+                IS DISTINCT FROM ($2, $3, $4, $5, $6)"
+    )
+    .bind(group_id)
+    .bind(values.tweet_time)
+    .bind(values.image_url)
+    .bind(values.body)
+    .bind(values.alt)
+    .bind(values.content_warnings)
+    .execute(&mut *db_conn)
+    .await?;
+
+    if res.rows_affected() == 0 {
+        return Ok(false);
     }
+
+    // The downloaded image and thumbnail are cleared if the image has changed, so that they are
+    // downloaded again
+    sqlx::query(
+        "UPDATE robot_groups SET \
+            tweet_time = $2, body = $4, alt = $5, content_warnings = $6, \
+            image_path = CASE WHEN image_url = $3 THEN image_path END, \
+            image_thumb_path = CASE WHEN image_url = $3 THEN image_thumb_path END, \
+            image_url = $3 \
+        WHERE id = $1"
+    )
+    .bind(group_id)
+    .bind(values.tweet_time)
+    .bind(values.image_url)
+    .bind(values.body)
+    .bind(values.alt)
+    .bind(values.content_warnings)
+    .execute(&mut *db_conn)
+    .await?;
+
+    Ok(true)
 }
 
 //TODO: test duplicate robot id
+/// Inserts the robot into the given group. If the robot already exists and `update` is set, the
+/// existing robot is updated instead, as long as it belongs to the same group.
 async fn store_robot(
     db_conn: &mut PgConnection,
     robot: &Robot<'_>,
+    group: &StoredGroup,
     tweet_data: &RobotTweetData<'_>,
-) -> Result<IdentBuf, NotScribed>
+    update: bool,
+) -> Result<StoredRobot, NotScribed>
 {
     let ident = robot.ident();
     
     let res = sqlx::query(
         "INSERT INTO robots \
-            (id, prefix, suffix, plural, tweet_id, tweet_time, image_url, body, alt, content_warning) \
+            (id, group_id, prefix, suffix, plural, needs_review) \
         VALUES \
-            ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10) \
+            ($1, $2, $3, $4, $5, $6) \
         ON CONFLICT (id) DO NOTHING"
     )
     .bind(&ident)
+    .bind(group.id)
     .bind(robot.name.prefix.as_ref())
     .bind(robot.name.suffix.as_ref())
     .bind(robot.name.plural.as_ref().map(Cow::as_ref))
-    .bind(tweet_data.tweet_id)
-    .bind(tweet_data.tweet_time)
-    .bind(tweet_data.image_url)
-    .bind(tweet_data.body)
-    .bind(tweet_data.alt)
-    .bind(tweet_data.cw)
-    .execute(db_conn)
+    .bind(tweet_data.needs_review)
+    .execute(&mut *db_conn)
     .await
     .map_err(NotScribed::from)?;
 
-    match res.rows_affected() {
-        0 => Err(InvalidTweet::DuplicateRobot(ident).into()),
-        _ => Ok(ident),
+    if res.rows_affected() > 0 {
+        return Ok(StoredRobot { id: ident, outcome: StoreOutcome::Inserted });
+    }
+
+    if !update {
+        return Err(InvalidTweet::DuplicateRobot(ident).into());
     }
+
+    let existing_group_id: i32 = sqlx::query_scalar(
+        "SELECT group_id FROM robots WHERE id = $1 FOR UPDATE"
+    )
+    .bind(&ident)
+    .fetch_one(&mut *db_conn)
+    .await?;
+
+    // Never move a robot from a different group, since that means two tweets (or a tweet and a
+    // manually added robot) claim the same robot rather than the tweet having changed
+    if existing_group_id != group.id {
+        return Err(InvalidTweet::DuplicateRobot(ident).into());
+    }
+
+    let changed = update_robot(&mut *db_conn, &ident, &RobotValues {
+        prefix: robot.name.prefix.as_ref(),
+        suffix: robot.name.suffix.as_ref(),
+        plural: robot.name.plural.as_ref().map(Cow::as_ref),
+        needs_review: tweet_data.needs_review,
+    })
+    .await?;
+
+    // The robot counts as updated if the group it shares with the other robots of the tweet was
+    let outcome = match (changed, group.outcome) {
+        (false, StoreOutcome::Unchanged) => StoreOutcome::Unchanged,
+        _ => StoreOutcome::Updated,
+    };
+
+    Ok(StoredRobot { id: ident, outcome })
+}
+
+/// Flags the robots of an existing group which are not among the given robots parsed from its post
+/// for review. Returns the robots which were flagged.
+async fn flag_missing_robots(
+    db_conn: &mut PgConnection,
+    group_id: i32,
+    parsed: &Plural<StoredRobot>,
+) -> sqlx::Result<Vec<StoredRobot>>
+{
+    let parsed_ids = parsed
+        .iter()
+        .map(|robot| robot.id.clone())
+        .collect::<Vec<_>>();
+
+    let missing_ids: Vec<IdentBuf> = sqlx::query_scalar(
+        "SELECT id FROM robots \
+        WHERE group_id = $1 AND NOT needs_review AND NOT (id = ANY($2)) \
+        ORDER BY id"
+    )
+    .bind(group_id)
+    .bind(&parsed_ids)
+    .fetch_all(&mut *db_conn)
+    .await?;
+
+    let mut flagged = Vec::with_capacity(missing_ids.len());
+
+    for id in missing_ids {
+        if flag_robot_for_review(&mut *db_conn, &id).await? {
+            flagged.push(StoredRobot { id, outcome: StoreOutcome::Flagged });
+        }
+    }
+
+    Ok(flagged)
+}
+
+/// The values of a robot which are taken from parsing its post.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct RobotValues<'a> {
+    pub(crate) prefix: &'a str,
+    pub(crate) suffix: &'a str,
+    pub(crate) plural: Option<&'a str>,
+    pub(crate) needs_review: bool,
+}
+
+/// Updates an existing robot with the given values, recording its previous values in
+/// `robot_history` first. Nothing is written if none of the values have changed. Returns whether
+/// or not the robot changed.
+pub(crate) async fn update_robot(
+    db_conn: &mut PgConnection,
+    id: &IdentBuf,
+    values: &RobotValues<'_>,
+) -> sqlx::Result<bool>
+{
+    // Record the previous values of the robot, but only if any of them are about to change
+    let res = sqlx::query(
+        "INSERT INTO robot_history \
+            (robot_id, prefix, suffix, plural, needs_review) \
+        SELECT \
+            id, prefix, suffix, plural, needs_review \
+        FROM robots \
+        WHERE id = $1 \
+            AND (prefix, suffix, plural, needs_review) IS DISTINCT FROM ($2, $3, $4, $5)"
+    )
+    .bind(id)
+    .bind(values.prefix)
+    .bind(values.suffix)
+    .bind(values.plural)
+    .bind(values.needs_review)
+    .execute(&mut *db_conn)
+    .await?;
+
+    if res.rows_affected() == 0 {
+        return Ok(false);
+    }
+
+    sqlx::query(
+        "UPDATE robots SET prefix = $2, suffix = $3, plural = $4, needs_review = $5 WHERE id = $1"
+    )
+    .bind(id)
+    .bind(values.prefix)
+    .bind(values.suffix)
+    .bind(values.plural)
+    .bind(values.needs_review)
+    .execute(&mut *db_conn)
+    .await?;
+
+    Ok(true)
 }
 
-fn is_valid_robot_media(media: &Media) -> bool {
-    match media.media_type.as_str() {
+/// Flags an existing robot for review, recording its previous values in `robot_history` first.
+/// Nothing is written if the robot is already flagged. Returns whether or not the robot changed.
+pub(crate) async fn flag_robot_for_review(
+    db_conn: &mut PgConnection,
+    id: &IdentBuf,
+) -> sqlx::Result<bool>
+{
+    let res = sqlx::query(
+        "INSERT INTO robot_history \
+            (robot_id, prefix, suffix, plural, needs_review) \
+        SELECT \
+            id, prefix, suffix, plural, needs_review \
+        FROM robots \
+        WHERE id = $1 AND NOT needs_review"
+    )
+    .bind(id)
+    .execute(&mut *db_conn)
+    .await?;
+
+    if res.rows_affected() == 0 {
+        return Ok(false);
+    }
+
+    sqlx::query("UPDATE robots SET needs_review = TRUE WHERE id = $1")
+        .bind(id)
+        .execute(&mut *db_conn)
+        .await?;
+
+    Ok(true)
+}
+
+/// Stores the media of the robot group in order, replacing any media previously stored for it.
+/// Returns whether or not the stored media changed. The downloaded image and thumbnail of a media
+/// item are cleared if its url changes.
+pub(crate) async fn store_group_media(
+    db_conn: &mut PgConnection,
+    group_id: i32,
+    media: &[MediaEntry<'_>],
+) -> sqlx::Result<bool>
+{
+    let mut media_types = Vec::with_capacity(media.len());
+    let mut urls = Vec::with_capacity(media.len());
+    let mut alts = Vec::with_capacity(media.len());
+    let mut durations = Vec::with_capacity(media.len());
+
+    let mut variant_positions = Vec::new();
+    let mut variant_indices = Vec::new();
+    let mut variant_content_types = Vec::new();
+    let mut variant_bitrates = Vec::new();
+    let mut variant_urls = Vec::new();
+
+    for (position, entry) in media.iter().enumerate() {
+        media_types.push(entry.media_type);
+        urls.push(entry.url);
+        alts.push(entry.alt);
+        durations.push(entry.duration_millis.map(|duration| duration as i64));
+
+        for (index, variant) in entry.variants.iter().enumerate() {
+            variant_positions.push(position as i32);
+            variant_indices.push(index as i32);
+            variant_content_types.push(variant.content_type);
+            variant_bitrates.push(variant.bitrate.map(|bitrate| bitrate as i64));
+            variant_urls.push(variant.url);
+        }
+    }
+
+    // Downloaded files are cleared if the media's url changes, so that they are downloaded again
+    let upserted = sqlx::query(
+        "INSERT INTO robot_media (group_id, position, media_type, url, alt, duration_ms) \
+        SELECT $1, position - 1, media_type, url, alt, duration_ms \
+        FROM UNNEST($2::TEXT[], $3::TEXT[], $4::TEXT[], $5::INT8[]) \
+            WITH ORDINALITY AS media(media_type, url, alt, duration_ms, position) \
+        ON CONFLICT (group_id, position) DO UPDATE SET \
+            media_type = excluded.media_type, \
+            alt = excluded.alt, \
+            duration_ms = excluded.duration_ms, \
+            width = CASE WHEN robot_media.url = excluded.url THEN robot_media.width END, \
+            height = CASE WHEN robot_media.url = excluded.url THEN robot_media.height END, \
+            image_path = CASE WHEN robot_media.url = excluded.url THEN robot_media.image_path END, \
+            image_thumb_path = CASE WHEN robot_media.url = excluded.url THEN robot_media.image_thumb_path END, \
+            video_path = CASE WHEN robot_media.url = excluded.url THEN robot_media.video_path END, \
+            url = excluded.url \
+        WHERE (robot_media.media_type, robot_media.url, robot_media.alt, robot_media.duration_ms) \
+            IS DISTINCT FROM (excluded.media_type, excluded.url, excluded.alt, excluded.duration_ms)"
+    )
+    .bind(group_id)
+    .bind(&media_types)
+    .bind(&urls)
+    .bind(&alts)
+    .bind(&durations)
+    .execute(&mut *db_conn)
+    .await?
+    .rows_affected();
+
+    let deleted = sqlx::query("DELETE FROM robot_media WHERE group_id = $1 AND position >= $2")
+        .bind(group_id)
+        .bind(media.len() as i32)
+        .execute(&mut *db_conn)
+        .await?
+        .rows_affected();
+
+    sqlx::query("DELETE FROM robot_media_variants WHERE group_id = $1")
+        .bind(group_id)
+        .execute(&mut *db_conn)
+        .await?;
+
+    if !variant_urls.is_empty() {
+        sqlx::query(
+            "INSERT INTO robot_media_variants (group_id, position, variant, content_type, bitrate, url) \
+            SELECT $1, position, variant, content_type, bitrate, url \
+            FROM UNNEST($2::INT4[], $3::INT4[], $4::TEXT[], $5::INT8[], $6::TEXT[]) \
+                AS variants(position, variant, content_type, bitrate, url)"
+        )
+        .bind(group_id)
+        .bind(&variant_positions)
+        .bind(&variant_indices)
+        .bind(&variant_content_types)
+        .bind(&variant_bitrates)
+        .bind(&variant_urls)
+        .execute(&mut *db_conn)
+        .await?;
+    }
+
+    Ok(upserted + deleted > 0)
+}
+
+/// Stores the hashtags, mentions and urls of the tweet, so that robots can be searched and linked
+/// by them.
+async fn store_entities(
+    db_conn: &mut PgConnection,
+    tweet_id: u64,
+    post: &RobotPost,
+) -> sqlx::Result<()>
+{
+    let tweet_id = tweet_id as i64;
+
+    // Remove any entities stored by a previous version of the tweet
+    for table in ["tweet_hashtags", "tweet_mentions", "tweet_urls"] {
+        sqlx::query(&format!("DELETE FROM {} WHERE tweet_id = $1", table))
+            .bind(tweet_id)
+            .execute(&mut *db_conn)
+            .await?;
+    }
+
+    if !post.hashtags.is_empty() {
+        let tags = post.hashtags
+            .iter()
+            .map(String::as_str)
+            .collect::<Vec<_>>();
+
+        sqlx::query(
+            "INSERT INTO tweet_hashtags (tweet_id, position, tag) \
+            SELECT $1, position - 1, tag FROM UNNEST($2::TEXT[]) WITH ORDINALITY AS tags(tag, position) \
+            ON CONFLICT DO NOTHING"
+        )
+        .bind(tweet_id)
+        .bind(&tags)
+        .execute(&mut *db_conn)
+        .await?;
+    }
+
+    if !post.mentions.is_empty() {
+        let (user_ids, handles) = post.mentions
+            .iter()
+            .map(|mention| (mention.user_id as i64, mention.handle.as_str()))
+            .unzip::<_, _, Vec<_>, Vec<_>>();
+
+        sqlx::query(
+            "INSERT INTO tweet_mentions (tweet_id, position, user_id, handle) \
+            SELECT $1, position - 1, user_id, handle \
+            FROM UNNEST($2::INT8[], $3::TEXT[]) WITH ORDINALITY AS mentions(user_id, handle, position) \
+            ON CONFLICT DO NOTHING"
+        )
+        .bind(tweet_id)
+        .bind(&user_ids)
+        .bind(&handles)
+        .execute(&mut *db_conn)
+        .await?;
+    }
+
+    if !post.urls.is_empty() {
+        let mut urls = Vec::with_capacity(post.urls.len());
+        let mut expanded_urls = Vec::with_capacity(post.urls.len());
+        let mut display_urls = Vec::with_capacity(post.urls.len());
+
+        for url in &post.urls {
+            urls.push(url.url.as_str());
+            expanded_urls.push(url.expanded_url.as_str());
+            display_urls.push(url.display_url.as_str());
+        }
+
+        sqlx::query(
+            "INSERT INTO tweet_urls (tweet_id, position, url, expanded_url, display_url) \
+            SELECT $1, position - 1, url, expanded_url, display_url \
+            FROM UNNEST($2::TEXT[], $3::TEXT[], $4::TEXT[]) \
+                WITH ORDINALITY AS urls(url, expanded_url, display_url, position) \
+            ON CONFLICT DO NOTHING"
+        )
+        .bind(tweet_id)
+        .bind(&urls)
+        .bind(&expanded_urls)
+        .bind(&display_urls)
+        .execute(&mut *db_conn)
+        .await?;
+    }
+
+    Ok(())
+}
+
+/// Stores the full text, entities and media of the tweet, so that it can be reparsed later without
+/// fetching it again. The indices of the urls and media links are kept so that reparsing can leave
+/// them out of the text in the same way as scribing does.
+async fn store_raw_tweet(
+    db_conn: &mut PgConnection,
+    tweet_id: u64,
+    post: &RobotPost,
+) -> sqlx::Result<()>
+{
+    let entities = serde_json::json!({
+        "hashtags": post.hashtags,
+
+        "mentions": post.mentions
+            .iter()
+            .map(|mention| serde_json::json!({
+                "user_id": mention.user_id,
+                "handle": mention.handle,
+                "display_name": mention.display_name,
+            }))
+            .collect::<Vec<_>>(),
+
+        "urls": post.urls
+            .iter()
+            .map(|url| serde_json::json!({
+                "url": url.url,
+                "expanded_url": url.expanded_url,
+                "display_url": url.display_url,
+                "indices": url.indices,
+            }))
+            .collect::<Vec<_>>(),
+    });
+
+    sqlx::query(
+        "INSERT INTO raw_tweets (tweet_id, tweet_time, full_text, entities, media) \
+        VALUES ($1, $2, $3, $4, $5) \
+        ON CONFLICT (tweet_id) DO UPDATE SET \
+            tweet_time = excluded.tweet_time, full_text = excluded.full_text, \
+            entities = excluded.entities, media = excluded.media, stored_at = now()"
+    )
+    .bind(tweet_id as i64)
+    .bind(post.time)
+    .bind(&post.full_text)
+    .bind(Json(entities))
+    .bind(Json(&post.media))
+    .execute(db_conn)
+    .await?;
+
+    Ok(())
+}
+
+/// Records a tweet which could not be stored and the reason why. If the tweet is already
+/// quarantined, its text and reason are refreshed, but it stays dismissed if it was dismissed.
+async fn store_quarantined(
+    db_conn: &mut PgConnection,
+    tweet_id: u64,
+    post: &RobotPost,
+    reason: &InvalidTweet,
+) -> sqlx::Result<()>
+{
+    sqlx::query(
+        "INSERT INTO quarantine (tweet_id, tweet_time, text, reason) \
+        VALUES ($1, $2, $3, $4) \
+        ON CONFLICT (tweet_id) DO UPDATE SET \
+            tweet_time = excluded.tweet_time, text = excluded.text, reason = excluded.reason, \
+            last_seen_at = now()"
+    )
+    .bind(tweet_id as i64)
+    .bind(post.time)
+    .bind(&post.text)
+    .bind(reason.to_string())
+    .execute(db_conn)
+    .await?;
+
+    Ok(())
+}
+
+pub(crate) fn is_valid_robot_media_type(media_type: &str) -> bool {
+    match media_type {
         "photo" | "animated_gif" | "video" => true,
         _ => false,
     }
@@ -209,18 +1148,36 @@ impl From<tokio::task::JoinError> for NotScribed {
 
 #[derive(Debug)]
 pub(crate) enum InvalidTweet {
-    ParseUnsuccessful,
+    ParseUnsuccessful {
+        error: ParseError,
+        /// The portion of the tweet text where the parse error occurred.
+        context: String,
+    },
+    ParseWarnings(Vec<ParseWarning>),
     MissingMedia,
     DuplicateRobot(IdentBuf),
+    AlreadyStored,
     NoRobots,
 }
 
 impl fmt::Display for InvalidTweet {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
-            Self::ParseUnsuccessful => write!(f, "could not parse robot data from tweet"),
+            Self::ParseUnsuccessful { error, context } => write!(
+                f,
+                "could not parse robot data from tweet ({}): {}, near {:?}",
+                error.stage(), error, context
+            ),
+            Self::ParseWarnings(warnings) => {
+                write!(f, "robot data parsed with warnings")?;
+                for (i, warning) in warnings.iter().enumerate() {
+                    write!(f, "{} {}", if i == 0 { ":" } else { ";" }, warning)?;
+                }
+                Ok(())
+            },
             Self::MissingMedia => write!(f, "tweet does not contain media"),
             Self::DuplicateRobot(ident) => write!(f, "robot {} already exists", ident),
+            Self::AlreadyStored => write!(f, "tweet has already been stored"),
             Self::NoRobots => write!(f, "no robots in tweet"),
         }
     }
@@ -1,12 +1,15 @@
 use std::collections::HashSet;
+use std::path::PathBuf;
 
 use anyhow::Context;
 use clap::Parser;
 use goldcrest::{TweetOptions, TimelineOptions, UserIdentifier};
 use sqlx::postgres::{PgPool, PgConnection};
 
-use crate::scribe::{self, ScribeFailure};
-use crate::model::{self, IdentBuf};
+use crate::report::IngestRecorder;
+use crate::scribe::{self, InvalidTweet, PostSource, ScribeFailure, ScribeOptions, ScribedPost};
+use crate::model;
+use crate::overrides::ParseOverrides;
 
 #[derive(Parser, Debug)]
 pub(crate) struct Opts {
@@ -22,6 +25,23 @@ pub(crate) struct Opts {
     #[clap(short, long)]
     verbose: bool,
 
+    /// Skip robot Tweets which parsed with warnings, rather than storing them flagged for review.
+    #[clap(long)]
+    strict: bool,
+
+    /// Update robots which have already been stored, rather than skipping their Tweets.
+    #[clap(long)]
+    update: bool,
+
+    /// A json file of manual corrections for robot Tweets which cannot be parsed correctly.
+    #[clap(long)]
+    overrides: Option<PathBuf>,
+
+    /// Write a json report of what happened to each Tweet, along with totals and timing, to the
+    /// given file.
+    #[clap(long)]
+    report: Option<PathBuf>,
+
     /// The handle of the user whose timeline should be read.
     #[clap(default_value = "smolrobots")]
     user: String,
@@ -33,6 +53,8 @@ pub(crate) async fn run(
     opts: Opts
 ) -> anyhow::Result<()>
 {
+    let mut recorder = IngestRecorder::start("timeline");
+
     let user = UserIdentifier::Handle(opts.user
         .strip_prefix('@')
         .map(str::to_owned)
@@ -42,12 +64,23 @@ pub(crate) async fn run(
         .await
         .context("failed to connect to database")?;
 
-    let robot_ids = scribe_timeline(au_client, &mut db_conn, user, opts.page_length, opts.pages, opts.verbose)
+    let overrides = ParseOverrides::load(opts.overrides.as_deref()).await?;
+
+    let scribe_opts = ScribeOptions {
+        verbose: opts.verbose,
+        strict: opts.strict,
+        update: opts.update,
+    };
+
+    let scribed = scribe_timeline(au_client, &mut db_conn, user, opts.page_length, opts.pages, &overrides, scribe_opts)
         .await
         .context("failed getting robots from user timeline")?;
 
-    for robot_id in robot_ids {
-        println!("{}", robot_id);
+    scribe::report_stored(&scribe::stored_robots(&scribed), opts.update);
+
+    if let Some(report_path) = opts.report {
+        recorder.scribed(&scribed);
+        recorder.finish().write(&report_path).await?;
     }
 
     Ok(())
@@ -76,10 +109,11 @@ async fn scribe_timeline(
     user: goldcrest::UserIdentifier,
     page_length: u32,
     pages: usize,
-    verbose: bool
-) -> Result<Vec<IdentBuf>, ScribeFailure>
+    overrides: &ParseOverrides,
+    scribe_opts: ScribeOptions
+) -> Result<Vec<ScribedPost>, ScribeFailure>
 {
-    let mut group_ids = Vec::new();
+    let mut scribed = Vec::new();
     let mut max_id = None;
 
     for _ in 0..pages {
@@ -102,19 +136,35 @@ async fn scribe_timeline(
                 .collect::<Vec<_>>();
 
             // Get the ids of the tweets already in the database; there is no need to parse these
-            // tweets again. Filtering them out now also avoids the robots.id sequence from being
-            // unneccessarily incremented ON CONFLICT
+            // tweets again unless we are updating them. Filtering them out now also avoids the
+            // robots.id sequence from being unneccessarily incremented ON CONFLICT
             let existing_ids = sqlx::query_as::<_, model::TweetId>(
                 "SELECT tweet_id FROM UNNEST($1) as tweet_ids(tweet_id) \
-                WHERE EXISTS (SELECT 1 FROM robots WHERE robots.tweet_id = tweet_ids.tweet_id)"
+                WHERE NOT $2 AND EXISTS (SELECT 1 FROM robot_groups WHERE robot_groups.tweet_id = tweet_ids.tweet_id)"
             )
             .bind(all_ids)
+            .bind(scribe_opts.update)
             .fetch_all(&mut *db_conn)
             .await?
             .into_iter()
             .map(|row| row.tweet_id as u64)
             .collect::<HashSet<_>>();
 
+            // Include the tweets which are skipped for already being stored in the results, so that
+            // they appear in the ingest report
+            scribed.extend(tweets
+                .iter()
+                .map(scribe::tweet_original)
+                .filter(|tweet| user_matches_identifier(&tweet.user, &user))
+                .map(|tweet| tweet.id)
+                .filter(|id| existing_ids.contains(id))
+                .collect::<HashSet<_>>()
+                .into_iter()
+                .map(|tweet_id| ScribedPost {
+                    source: PostSource::Tweet(tweet_id),
+                    result: Err(InvalidTweet::AlreadyStored),
+                }));
+
             tweets.retain(|tweet| tweet.id > 0
                 // Check that the original tweet is from the specified user, since it may be a
                 // retweet of a different user's tweet
@@ -127,7 +177,7 @@ async fn scribe_timeline(
         };
 
         if tweets.is_empty() {
-            if verbose {
+            if scribe_opts.verbose {
                 eprintln!("empty timeline page reached, stopping");
             }
             break;
@@ -141,12 +191,12 @@ async fn scribe_timeline(
             .unwrap() - 1
         );
 
-        group_ids.extend(
-            scribe::scribe_tweets(&mut *db_conn, &tweets, verbose)
+        scribed.extend(
+            scribe::scribe_tweets(&mut *db_conn, &tweets, overrides, scribe_opts)
                 .await?
                 .into_iter()
         );
     }
 
-    Ok(group_ids)
+    Ok(scribed)
 }
@@ -0,0 +1,94 @@
+use std::collections::HashSet;
+
+use anyhow::Context;
+use clap::Parser;
+use goldcrest::TweetOptions;
+use sqlx::postgres::PgPool;
+
+use crate::fetch::TWEETS_PER_REQUEST;
+
+#[derive(Parser, Debug)]
+pub(crate) struct Opts {
+    /// Display additional information.
+    #[clap(short, long)]
+    verbose: bool,
+
+    /// Also check Tweets which have already been found to be deleted, clearing the mark from any
+    /// which have reappeared.
+    #[clap(short, long)]
+    all: bool,
+}
+
+pub(crate) async fn run(
+    db_pool: &PgPool,
+    au_client: &goldcrest::Client,
+    opts: Opts
+) -> anyhow::Result<()>
+{
+    let tweet_ids: Vec<i64> = sqlx::query_scalar(
+        "SELECT tweet_id FROM robot_groups \
+        WHERE tweet_id IS NOT NULL AND ($1 OR deleted_upstream_at IS NULL) \
+        ORDER BY tweet_id"
+    )
+    .bind(opts.all)
+    .fetch_all(db_pool)
+    .await
+    .context("failed to get stored tweet ids")?;
+
+    let (mut n_deleted, mut n_restored) = (0usize, 0usize);
+
+    for ids in tweet_ids.chunks(TWEETS_PER_REQUEST) {
+        let tweets = au_client
+            .get_tweets(ids.iter().map(|&id| id as u64).collect(), TweetOptions::default())
+            .await
+            .context("failed to fetch tweets")?;
+
+        let received = tweets
+            .iter()
+            .map(|tweet| tweet.id as i64)
+            .collect::<HashSet<_>>();
+
+        let (found, missing) = ids
+            .iter()
+            .partition::<Vec<i64>, _>(|id| received.contains(id));
+
+        // Only set the deletion time the first time the tweet is found to be missing
+        let deleted: Vec<i64> = sqlx::query_scalar(
+            "UPDATE robot_groups SET deleted_upstream_at = now() \
+            WHERE tweet_id = ANY($1) AND deleted_upstream_at IS NULL \
+            RETURNING tweet_id"
+        )
+        .bind(&missing)
+        .fetch_all(db_pool)
+        .await
+        .context("failed to mark deleted tweets")?;
+
+        let restored: Vec<i64> = sqlx::query_scalar(
+            "UPDATE robot_groups SET deleted_upstream_at = NULL \
+            WHERE tweet_id = ANY($1) AND deleted_upstream_at IS NOT NULL \
+            RETURNING tweet_id"
+        )
+        .bind(&found)
+        .fetch_all(db_pool)
+        .await
+        .context("failed to unmark restored tweets")?;
+
+        // Print the newly deleted tweets to stdout so that they can be piped into other commands
+        for id in &deleted {
+            println!("{}", id);
+        }
+
+        if opts.verbose {
+            for id in &restored {
+                eprintln!("tweet {} is available again", id);
+            }
+        }
+
+        n_deleted += deleted.len();
+        n_restored += restored.len();
+    }
+
+    eprintln!("{} tweets checked, {} newly deleted, {} available again", tweet_ids.len(), n_deleted, n_restored);
+
+    Ok(())
+}